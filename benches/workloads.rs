@@ -0,0 +1,67 @@
+#[macro_use] extern crate criterion;
+extern crate libdb;
+
+use criterion::Criterion;
+use libdb::bench::{self, KeyDistribution, Workload};
+
+const KEYS: u32 = 10000;
+const OPS: usize = 1000;
+
+/// Helper to open an empty in-memory database.
+fn open_empty_db() -> libdb::Database {
+    libdb::DatabaseBuilder::new()
+        .flags(libdb::DB_CREATE)
+        .open()
+        .expect("Failed to open DB")
+}
+
+/// Helper to open an in-memory database loaded with the workload's keys.
+fn open_loaded_db(workload: &Workload) -> libdb::Database {
+    let db = open_empty_db();
+    bench::load(&db, workload).expect("Failed to load DB");
+    db
+}
+
+fn point_get(c: &mut Criterion) {
+    for &(name, distribution) in &[("sequential", KeyDistribution::Sequential),
+                                   ("uniform", KeyDistribution::Uniform),
+                                   ("hotspot", KeyDistribution::Hotspot(0.2, 0.8))] {
+        let workload = Workload::new(KEYS, 100).distribution(distribution);
+        let db = open_loaded_db(&workload);
+        c.bench_function(&format!("point_get/{}", name), |b| {
+            b.iter(|| bench::point_gets(&db, &workload, OPS).unwrap())
+        });
+    }
+}
+
+fn point_put(c: &mut Criterion) {
+    let workload = Workload::new(KEYS, 100).distribution(KeyDistribution::Uniform);
+    let db = open_loaded_db(&workload);
+    c.bench_function("point_put/uniform", |b| {
+        b.iter(|| bench::point_puts(&db, &workload, OPS).unwrap())
+    });
+}
+
+fn scan(c: &mut Criterion) {
+    let workload = Workload::new(KEYS, 100);
+    let db = open_loaded_db(&workload);
+    c.bench_function("scan", |b| b.iter(|| bench::scan(&db).unwrap()));
+}
+
+fn bulk_load(c: &mut Criterion) {
+    let workload = Workload::new(KEYS, 100);
+    c.bench_function("bulk_load", |b| {
+        b.iter(|| bench::bulk_load(&open_empty_db(), &workload, 1024 * 1024).unwrap())
+    });
+}
+
+fn mixed(c: &mut Criterion) {
+    let workload = Workload::new(KEYS, 100).distribution(KeyDistribution::Hotspot(0.2, 0.8));
+    let db = open_loaded_db(&workload);
+    c.bench_function("mixed/90_read", |b| {
+        b.iter(|| bench::mixed(&db, &workload, OPS, 0.9).unwrap())
+    });
+}
+
+criterion_group!(benches, point_get, point_put, scan, bulk_load, mixed);
+criterion_main!(benches);
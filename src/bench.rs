@@ -0,0 +1,212 @@
+//! Reusable workload drivers for benchmarking the bindings.
+//!
+//! The drivers only use the public API, so they measure what an application pays for each
+//! operation, including the cost of building and freeing DBTs. They are enabled with the
+//! `bench` feature and used by the criterion suite in `benches/`.
+//!
+//! # Examples
+//! ```
+//! use libdb::bench::{self, KeyDistribution, Workload};
+//!
+//! let db = libdb::DatabaseBuilder::new()
+//!     .flags(libdb::DB_CREATE)
+//!     .open()
+//!     .unwrap();
+//!
+//! let workload = Workload::new(1000, 64).distribution(KeyDistribution::Uniform);
+//! bench::bulk_load(&db, &workload, 64 * 1024).unwrap();
+//! assert_eq!(1000, bench::scan(&db).unwrap());
+//! assert_eq!(100, bench::point_gets(&db, &workload, 100).unwrap());
+//! ```
+
+use super::db::Database;
+use super::error::Error;
+use super::flags::DB_NONE;
+
+/// How a workload chooses the key for each operation.
+#[derive(Clone, Copy, Debug)]
+pub enum KeyDistribution {
+    /// Keys in increasing order, wrapping around after the last key.
+    Sequential,
+    /// Every key is equally likely.
+    Uniform,
+    /// A fraction of the keys (the first field) receives a fraction of the operations
+    /// (the second field), e.g. `Hotspot(0.2, 0.8)`.
+    Hotspot(f64, f64),
+}
+
+/// The shape of a benchmark workload.
+#[derive(Clone, Debug)]
+pub struct Workload {
+    key_count: u32,
+    value_size: usize,
+    distribution: KeyDistribution,
+    seed: u64,
+}
+
+impl Workload {
+    /// Create a workload over `key_count` keys with values of `value_size` bytes.
+    ///
+    /// # Panics
+    /// Panics if `key_count` is zero.
+    pub fn new(key_count: u32, value_size: usize) -> Workload {
+        assert!(key_count > 0, "a workload needs at least one key");
+        Workload {
+            key_count: key_count,
+            value_size: value_size,
+            distribution: KeyDistribution::Sequential,
+            seed: 0x2545F4914F6CDD1D,
+        }
+    }
+
+    /// Set the key distribution.
+    ///
+    /// # Panics
+    /// Panics if a `Hotspot`'s fraction of keys is not in `(0, 1]`, or its fraction of
+    /// operations is not in `[0, 1]`.
+    pub fn distribution(mut self, distribution: KeyDistribution) -> Self {
+        if let KeyDistribution::Hotspot(keys, ops) = distribution {
+            assert!(keys > 0.0 && keys <= 1.0, "hotspot key fraction must be in (0, 1]");
+            assert!(ops >= 0.0 && ops <= 1.0, "hotspot operation fraction must be in [0, 1]");
+        }
+        self.distribution = distribution;
+        self
+    }
+
+    /// Set the seed for randomly distributed keys, so runs can be repeated exactly.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Return an endless iterator over the workload's keys.
+    pub fn keys(&self) -> Keys {
+        Keys {
+            workload: self.clone(),
+            next: 0,
+            state: self.seed | 1,
+        }
+    }
+
+    /// Return a value of the workload's value size.
+    pub fn value(&self) -> Vec<u8> {
+        (0..self.value_size).map(|i| i as u8).collect()
+    }
+}
+
+/// An iterator over the keys chosen by a `Workload`.
+pub struct Keys {
+    workload: Workload,
+    next: u32,
+    state: u64,
+}
+
+impl Keys {
+    /// xorshift64*; good enough to spread keys and cheap enough not to show up in profiles.
+    fn random(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    /// A random number in `[0, 1)`.
+    fn fraction(&mut self) -> f64 {
+        (self.random() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Iterator for Keys {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let count = self.workload.key_count;
+        let index = match self.workload.distribution {
+            KeyDistribution::Sequential => {
+                let index = self.next;
+                self.next = (self.next + 1) % count;
+                index
+            },
+            KeyDistribution::Uniform => (self.random() % count as u64) as u32,
+            KeyDistribution::Hotspot(keys, ops) => {
+                let hot = ((count as f64 * keys) as u32).max(1).min(count);
+                if self.fraction() < ops || hot == count {
+                    (self.random() % hot as u64) as u32
+                } else {
+                    hot + (self.random() % (count - hot) as u64) as u32
+                }
+            },
+        };
+        Some(key(index))
+    }
+}
+
+/// Encode a key index so that keys sort in index order.
+pub fn key(index: u32) -> Vec<u8> {
+    index.to_be_bytes().to_vec()
+}
+
+/// Insert every key in the workload, in order.
+pub fn load(db: &Database, workload: &Workload) -> Result<(), Error> {
+    let value = workload.value();
+    for index in 0..workload.key_count {
+        db.put(None, key(index).as_slice(), value.as_slice(), DB_NONE)?;
+    }
+    Ok(())
+}
+
+/// Insert every key in the workload, in order, through a `BulkWriter` with a buffer of
+/// `buffer_size` bytes.
+pub fn bulk_load(db: &Database, workload: &Workload, buffer_size: usize) -> Result<(), Error> {
+    let value = workload.value();
+    let mut writer = db.bulk_writer(None, buffer_size);
+    for index in 0..workload.key_count {
+        writer.put(key(index).as_slice(), value.as_slice())?;
+    }
+    writer.flush()
+}
+
+/// Perform `ops` gets, returning how many keys were found.
+pub fn point_gets(db: &Database, workload: &Workload, ops: usize) -> Result<usize, Error> {
+    let mut found = 0;
+    for key in workload.keys().take(ops) {
+        if db.get(None, key.as_slice(), DB_NONE)?.is_some() {
+            found += 1;
+        }
+    }
+    Ok(found)
+}
+
+/// Perform `ops` puts.
+pub fn point_puts(db: &Database, workload: &Workload, ops: usize) -> Result<(), Error> {
+    let value = workload.value();
+    for key in workload.keys().take(ops) {
+        db.put(None, key.as_slice(), value.as_slice(), DB_NONE)?;
+    }
+    Ok(())
+}
+
+/// Read every record with a cursor, returning the number of records.
+pub fn scan(db: &Database) -> Result<usize, Error> {
+    let mut count = 0;
+    let mut cursor = db.cursor(None, DB_NONE)?;
+    while let Some(_) = cursor.next()? {
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Perform `ops` operations, of which `read_fraction` are gets and the rest puts.
+pub fn mixed(db: &Database, workload: &Workload, ops: usize, read_fraction: f64) -> Result<(), Error> {
+    let value = workload.value();
+    let mut keys = workload.keys();
+    for _ in 0..ops {
+        let key = keys.next().unwrap();
+        if keys.fraction() < read_fraction {
+            db.get(None, key.as_slice(), DB_NONE)?;
+        } else {
+            db.put(None, key.as_slice(), value.as_slice(), DB_NONE)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! End-to-end checksumming of values.
+//!
+//! `ChecksummedDb` stores each value behind a CRC-32 of its bytes and verifies it on every
+//! read, so torn or corrupted values are detected even in files which were created without
+//! `DB_CHKSUM`. Values written through the wrapper must only be read through it.
+
+use super::db::{Database, Transaction};
+use super::error::{Error, ErrorKind};
+use super::flags::Flags;
+
+/// The length of the checksum stored before each value.
+const CHECKSUM_LEN: usize = 4;
+
+/// A database whose values carry a CRC-32 checksum.
+///
+/// # Examples
+/// ```
+/// let db = libdb::DatabaseBuilder::new()
+///     .flags(libdb::DB_CREATE)
+///     .open()
+///     .unwrap();
+/// let checked = libdb::ChecksummedDb::new(db.clone());
+///
+/// let key = String::from("key").into_bytes();
+/// checked.put(None, key.as_slice(), b"value", libdb::DB_NONE).unwrap();
+/// let ret = checked.get(None, key.as_slice(), libdb::DB_NONE).unwrap();
+/// assert_eq!(b"value", ret.unwrap().as_slice());
+///
+/// // A value stored without a checksum fails verification.
+/// let value = String::from("value").into_bytes();
+/// db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+/// let err = checked.get(None, key.as_slice(), libdb::DB_NONE).unwrap_err();
+/// assert_eq!(libdb::ErrorKind::ChecksumMismatch, err.kind());
+/// ```
+pub struct ChecksummedDb {
+    db: Database,
+}
+
+impl ChecksummedDb {
+    /// Wrap a database handle.
+    pub fn new(db: Database) -> ChecksummedDb {
+        ChecksummedDb { db: db }
+    }
+
+    /// Return the underlying database handle.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Get a value, verifying its checksum.
+    ///
+    /// Fails with `ErrorKind::ChecksumMismatch` if the stored value does not match its
+    /// checksum.
+    pub fn get(&self, txn: Option<&Transaction>, key: &[u8], flags: Flags) -> Result<Option<Vec<u8>>, Error> {
+        let stored = match self.db.get(txn, key, flags)? {
+            Some(stored) => stored,
+            None         => return Ok(None),
+        };
+        if stored.len() < CHECKSUM_LEN {
+            return Err(Error::from_kind(ErrorKind::ChecksumMismatch));
+        }
+
+        let (checksum, value) = stored.split_at(CHECKSUM_LEN);
+        if checksum != &crc32(value).to_le_bytes()[..] {
+            return Err(Error::from_kind(ErrorKind::ChecksumMismatch));
+        }
+        Ok(Some(value.to_vec()))
+    }
+
+    /// Store a value behind its checksum.
+    ///
+    /// The stored value is four bytes longer than `data`, which counts against the
+    /// database's value size limit.
+    pub fn put(&self, txn: Option<&Transaction>, key: &[u8], data: &[u8], flags: Flags) -> Result<(), Error> {
+        let mut stored = Vec::with_capacity(CHECKSUM_LEN + data.len());
+        stored.extend_from_slice(&crc32(data).to_le_bytes());
+        stored.extend_from_slice(data);
+        self.db.put(txn, key, stored.as_slice(), flags)
+    }
+}
+
+/// The CRC-32 (IEEE 802.3) lookup table.
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Compute the CRC-32 of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
@@ -1,14 +1,28 @@
-use std::ffi::CString;
-use std::path::Path;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use std::mem;
+use std::io::{self, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::os::raw::{c_char, c_long, c_void};
+use std::path::{Path, PathBuf};
 use std::ptr;
-use std::sync::Arc;
+use std::slice;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use libc;
 use libdb_sys::ffi as db_ffi;
 
 use super::dbt::DBT;
 use super::error;
-use super::error::Error;
+use super::error::{Error, ErrorKind};
 use super::flags::*;
+use super::service::ServiceSet;
+use super::stat::{CacheStat, LockStat, LogStat, Lsn, MutexStat, TxnStat};
 
 pub type Environment = Arc<Env>;
 pub type Database = Arc<Db>;
@@ -19,6 +33,34 @@ pub struct EnvironmentBuilder {
     home: Option<CString>,
     flags: Flags,
     mode: i32,
+    read_only: bool,
+    tx_timestamp: Option<SystemTime>,
+    cache_size: Option<(u32, u32, i32)>,
+    mp_mmapsize: Option<usize>,
+    mp_max_openfd: Option<u32>,
+    mp_max_write: Option<(u32, Duration)>,
+    lk_detect: Option<DeadlockPolicy>,
+    lg_max: Option<u32>,
+    lg_bsize: Option<u32>,
+    lg_dir: Option<CString>,
+    data_dirs: Vec<CString>,
+    tmp_dir: Option<CString>,
+    create_dir: Option<CString>,
+    dir_mode: Option<CString>,
+    shm_key: Option<i64>,
+    thread_count: Option<u32>,
+    passphrase: Option<CString>,
+    mutex_increment: Option<u32>,
+    lk_max_lockers: Option<u32>,
+    lk_max_locks: Option<u32>,
+    lk_max_objects: Option<u32>,
+    lk_partitions: Option<u32>,
+    #[cfg(feature = "v5_3")]
+    memory_init: Vec<(RegionObject, u32)>,
+    #[cfg(feature = "v5_3")]
+    memory_max: Option<(u32, u32)>,
+    // Referenced by libdb through DB_ENV->app_private; handed to the Env once open.
+    ctx: Box<EnvContext>,
 }
 
 impl EnvironmentBuilder {
@@ -29,14 +71,45 @@ impl EnvironmentBuilder {
     pub fn new() -> EnvironmentBuilder {
         unsafe {
             let mut env_ptr: *mut db_ffi::DB_ENV = ptr::null_mut();
-            let ret = db_ffi::db_env_create(&mut env_ptr, 0);
+            let ret = ffi_fn!(db_env_create, &mut env_ptr, 0);
             match ret {
-                0 => EnvironmentBuilder {
+                0 => {
+                    let mut ctx = Box::new(EnvContext::new());
+                    (*env_ptr).app_private = &mut *ctx as *mut EnvContext as *mut c_void;
+                    EnvironmentBuilder {
                         env_ptr: env_ptr,
                         home: None,
                         flags: DB_NONE,
                         mode: 0,
-                    },
+                        read_only: false,
+                        tx_timestamp: None,
+                        cache_size: None,
+                        mp_mmapsize: None,
+                        mp_max_openfd: None,
+                        mp_max_write: None,
+                        lk_detect: None,
+                        lg_max: None,
+                        lg_bsize: None,
+                        lg_dir: None,
+                        data_dirs: Vec::new(),
+                        tmp_dir: None,
+                        create_dir: None,
+                        dir_mode: None,
+                        shm_key: None,
+                        thread_count: None,
+                        passphrase: None,
+                        mutex_increment: None,
+                        lk_max_lockers: None,
+                        lk_max_locks: None,
+                        lk_max_objects: None,
+                        lk_partitions: None,
+                        #[cfg(feature = "v5_3")]
+                        memory_init: Vec::new(),
+                        #[cfg(feature = "v5_3")]
+                        memory_max: None,
+                        ctx: ctx,
+                    }
+                },
                 e => panic!("Could not instantiate DB_ENV: {}", e)
             }
         }
@@ -60,8 +133,630 @@ impl EnvironmentBuilder {
         self
     }
 
+    /// Make the environment read-only.
+    ///
+    /// Databases opened in a read-only environment are opened `DB_RDONLY`; asking to create
+    /// or truncate one fails, as does any write through its handle, with `ErrorKind::ReadOnly`.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// # {
+    /// #     let env = libdb::EnvironmentBuilder::new()
+    /// #         .home(dir.path())
+    /// #         .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    /// #         .open()
+    /// #         .unwrap();
+    /// #     libdb::DatabaseBuilder::new()
+    /// #         .environment(&env)
+    /// #         .file("db")
+    /// #         .flags(libdb::DB_CREATE)
+    /// #         .open()
+    /// #         .unwrap();
+    /// # }
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .read_only()
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .file("db")
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// let ret = db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE);
+    /// assert_eq!(libdb::ErrorKind::ReadOnly, ret.unwrap_err().kind());
+    ///
+    /// let ret = env.lsn_reset("db", false);
+    /// assert_eq!(libdb::ErrorKind::ReadOnly, ret.unwrap_err().kind());
+    /// let ret = env.fileid_reset("db", false);
+    /// assert_eq!(libdb::ErrorKind::ReadOnly, ret.unwrap_err().kind());
+    /// # }
+    /// ```
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Recover the environment to its state at `timestamp`, from archived log files.
+    ///
+    /// Transactions which committed after the timestamp are rolled back. Only used with
+    /// `DB_RECOVER_FATAL`, and the log files reaching back before the timestamp must be in
+    /// the environment's log directory. Opening fails with `EINVAL` if `timestamp` is before
+    /// the Unix epoch.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home("/var/lib/app/db")
+    ///     .flags(libdb::DB_CREATE | libdb::DB_RECOVER_FATAL | libdb::DB_INIT_LOCK |
+    ///            libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .tx_timestamp(an_hour_ago)
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn tx_timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.tx_timestamp = Some(timestamp);
+        self
+    }
+
+    /// Set the size of the shared memory cache to `gbytes` gigabytes plus `bytes` bytes,
+    /// split into `ncache` separately allocated regions.
+    ///
+    /// The default cache of 256KB is only suitable for testing. Caches under 500MB are
+    /// enlarged by 25% to account for overhead. `ncache` of 0 or 1 allocates one region;
+    /// more are needed where the system cannot allocate a single region of the full size.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .cache_size(0, 64 * 1024 * 1024, 1)
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn cache_size(mut self, gbytes: u32, bytes: u32, ncache: i32) -> Self {
+        self.cache_size = Some((gbytes, bytes, ncache));
+        self
+    }
+
+    /// Set the largest file, in bytes, which is mapped into memory rather than read through
+    /// the cache when opened read-only.
+    ///
+    /// Mapping files saves copying their pages into the cache. The default is 10MB.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .mmap_size(64 * 1024 * 1024)
+    ///     .max_open_files(100)
+    ///     .max_write(16, Duration::from_millis(5))
+    ///     .open()
+    ///     .unwrap();
+    /// assert_eq!((16, Duration::from_millis(5)), env.mp_max_write().unwrap());
+    /// ```
+    pub fn mmap_size(mut self, bytes: usize) -> Self {
+        self.mp_mmapsize = Some(bytes);
+        self
+    }
+
+    /// Limit how many file descriptors the cache keeps open to write pages back to
+    /// database files.
+    ///
+    /// By default descriptors stay open until their databases are closed.
+    pub fn max_open_files(mut self, count: u32) -> Self {
+        self.mp_max_openfd = Some(count);
+        self
+    }
+
+    /// Limit how many dirty pages the cache writes at a time, sleeping for `sleep` between
+    /// batches, as `Env::set_mp_max_write` does once the environment is open.
+    ///
+    /// Opening fails with `EINVAL` if `max_write` exceeds `i32::MAX`.
+    pub fn max_write(mut self, max_write: u32, sleep: Duration) -> Self {
+        self.mp_max_write = Some((max_write, sleep));
+        self
+    }
+
+    /// Run the deadlock detector whenever a lock request conflicts, rejecting requests
+    /// chosen by `policy` to break any deadlock it finds.
+    ///
+    /// Without this, deadlocked transactions wait until a lock timeout expires, or until
+    /// the application runs the detector itself.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .deadlock_policy(libdb::DeadlockPolicy::Youngest)
+    ///     .open()
+    ///     .unwrap();
+    /// assert_eq!(Some(libdb::DeadlockPolicy::Youngest), env.deadlock_policy().unwrap());
+    /// # }
+    /// ```
+    pub fn deadlock_policy(mut self, policy: DeadlockPolicy) -> Self {
+        self.lk_detect = Some(policy);
+        self
+    }
+
+    /// Set the size at which the log rolls over to a new file, in bytes.
+    ///
+    /// The default is 10MB. The size must be at least four times the log buffer size.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .log_file_size(64 * 1024 * 1024)
+    ///     .log_buffer_size(1024 * 1024)
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn log_file_size(mut self, bytes: u32) -> Self {
+        self.lg_max = Some(bytes);
+        self
+    }
+
+    /// Set the size of the in-memory log buffer, in bytes.
+    ///
+    /// The default is 32KB, or 1MB for in-memory logs. A larger buffer lets write-heavy
+    /// workloads batch more log records per write.
+    pub fn log_buffer_size(mut self, bytes: u32) -> Self {
+        self.lg_bsize = Some(bytes);
+        self
+    }
+
+    /// Keep the log files in `dir` rather than the environment home directory.
+    ///
+    /// A relative path is taken relative to the home directory. Putting the logs on a
+    /// different disk than the databases spreads the I/O, and lets the databases be
+    /// recovered from the logs if the data disk fails.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home("/var/lib/app/db")
+    ///     .log_dir("/var/log/app/db")
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn log_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.lg_dir = Some(CString::new(dir.as_ref().to_str().unwrap()).unwrap());
+        self
+    }
+
+    /// Add `dir` to the directories searched for database files.
+    ///
+    /// Call it once for each directory; they are searched in the order given, and new
+    /// databases are created in the first. Relative paths are taken relative to the home
+    /// directory, as are database files opened by relative name. This matches
+    /// `add_data_dir` lines in a `DB_CONFIG` file.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home("/var/lib/app/db")
+    ///     .data_dir("/mnt/disk1/app")
+    ///     .data_dir("/mnt/disk2/app")
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn data_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.data_dirs.push(CString::new(dir.as_ref().to_str().unwrap()).unwrap());
+        self
+    }
+
+    /// Create temporary files, such as the backing files of in-memory databases which
+    /// outgrow the cache, in `dir`.
+    ///
+    /// By default libdb tries the `TMPDIR` and `TEMP` environment variables and then a
+    /// list of system directories.
+    pub fn tmp_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.tmp_dir = Some(CString::new(dir.as_ref().to_str().unwrap()).unwrap());
+        self
+    }
+
+    /// Create new databases in `dir`, which must also be given to `data_dir`, rather than
+    /// the first data directory.
+    pub fn create_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.create_dir = Some(CString::new(dir.as_ref().to_str().unwrap()).unwrap());
+        self
+    }
+
+    /// Create any missing parent directories of the files the environment creates, with
+    /// permissions given as in `ls -l`, e.g. `"rwxr-x---"`.
+    ///
+    /// Without this, creating a database or log file in a directory which does not exist
+    /// fails.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .intermediate_dir_mode("rwx------")
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .file("tables/users/db")
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// assert!(dir.path().join("tables/users/db").exists());
+    /// # }
+    /// ```
+    pub fn intermediate_dir_mode(mut self, mode: &str) -> Self {
+        self.dir_mode = Some(CString::new(mode).unwrap());
+        self
+    }
+
+    /// Set the base segment ID of the System V shared memory regions used when the
+    /// environment is opened with `DB_SYSTEM_MEM`.
+    ///
+    /// Each process opening the environment must use the same key. Regions are allocated
+    /// IDs counting up from `key`, so keys for different environments should be spaced
+    /// apart. Opening fails with `EINVAL` if `key` does not fit in the platform's `long`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home("/var/lib/app/db")
+    ///     .shm_key(0x4442_0000)
+    ///     .flags(libdb::DB_CREATE | libdb::DB_SYSTEM_MEM | libdb::DB_INIT_LOCK |
+    ///            libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn shm_key(mut self, key: i64) -> Self {
+        self.shm_key = Some(key);
+        self
+    }
+
+    /// Set roughly how many threads of control, across every process, will use the
+    /// environment at once.
+    ///
+    /// This sizes the table `Env::failchk` uses to track threads, and is required for it.
+    pub fn thread_count(mut self, count: u32) -> Self {
+        self.thread_count = Some(count);
+        self
+    }
+
+    /// Set the function `Env::failchk` uses to ask whether a thread of control is still
+    /// alive. `failchk` fails with `EINVAL` if this is not set.
+    ///
+    /// It is called with a process ID, a thread ID, and whether only the process need be
+    /// checked, and must return false once the process (or thread) has exited. IDs are
+    /// those assigned by `thread_id`, by default the process ID and `pthread_self`. If it
+    /// panics, the thread is taken to be alive.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libc;
+    /// # extern crate libdb;
+    /// # fn main() {
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .thread_count(64)
+    ///     .is_alive(|pid, _tid, _process_only| unsafe { libc::kill(pid, 0) == 0 })
+    ///     .open()
+    ///     .unwrap();
+    /// env.failchk().unwrap();
+    /// # }
+    /// ```
+    pub fn is_alive<F>(mut self, is_alive: F) -> Self
+        where F: Fn(i32, u64, bool) -> bool + Send + Sync + 'static
+    {
+        self.ctx.is_alive = Some(Box::new(is_alive));
+        self
+    }
+
+    /// Set the function which identifies the calling thread of control as a process ID and
+    /// thread ID, for applications whose threads are not identified by `getpid` and
+    /// `pthread_self`. If it panics, those are used instead.
+    pub fn thread_id<F>(mut self, thread_id: F) -> Self
+        where F: Fn() -> (i32, u64) + Send + Sync + 'static
+    {
+        self.ctx.thread_id = Some(Box::new(thread_id));
+        self
+    }
+
+    /// Deliver libdb's detailed error messages to `on_error`, instead of discarding them.
+    ///
+    /// The errors returned to the application only carry an error number; the messages
+    /// explain them, e.g. naming the file which could not be opened. `on_error` is called
+    /// with the environment's error prefix (empty if none is set) and the message.
+    ///
+    /// Like `on_message`, `on_error` runs inside libdb while holding the lock which
+    /// serializes calls to it, so it must not call back into the environment or its handles.
+    /// A panic in `on_error` is caught and the message dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let messages = Arc::new(Mutex::new(Vec::new()));
+    /// let log = messages.clone();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .on_error(move |_prefix, message| log.lock().unwrap().push(message.to_string()))
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// // Transactions were not initialized.
+    /// assert!(env.txn(None, libdb::TxnFlags::new()).is_err());
+    /// assert!(!messages.lock().unwrap().is_empty());
+    /// ```
+    pub fn on_error<F>(mut self, on_error: F) -> Self
+        where F: FnMut(&str, &str) + Send + 'static
+    {
+        self.ctx.on_error = Some(Mutex::new(Box::new(on_error)));
+        self
+    }
+
+    /// Deliver libdb's informational messages, such as statistics printouts and `DB_VERB_*`
+    /// diagnostics, to `on_message` instead of writing them to stdout.
+    ///
+    /// `on_message` runs while holding a lock which serializes calls to it, and from inside
+    /// libdb. It must not call back into the environment or its handles: a call which prints
+    /// a message waits on that lock forever. A panic in `on_message` is caught and the
+    /// message dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let messages = Arc::new(Mutex::new(Vec::new()));
+    /// let log = messages.clone();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .on_message(move |message| log.lock().unwrap().push(message.to_string()))
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn on_message<F>(mut self, on_message: F) -> Self
+        where F: FnMut(&str) + Send + 'static
+    {
+        self.ctx.on_message = Some(Mutex::new(Box::new(on_message)));
+        self
+    }
+
+    /// Deliver environment events, such as a panic or a change of replication role, to
+    /// `on_event`.
+    ///
+    /// `on_event` is called from inside libdb, which may be holding its own locks, and while
+    /// holding a lock which serializes calls to it. It must not call back into the
+    /// environment or its handles, or it risks deadlocking; record the event and act on it
+    /// from another thread instead. A panic in `on_event` is caught and the event dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let events = Arc::new(Mutex::new(Vec::new()));
+    /// let pending = events.clone();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .on_event(move |event| match event {
+    ///         libdb::Event::Panic(_) | libdb::Event::RepMaster => pending.lock().unwrap().push(event),
+    ///         _                                                => (),
+    ///     })
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    pub fn on_event<F>(mut self, on_event: F) -> Self
+        where F: FnMut(Event) + Send + 'static
+    {
+        self.ctx.on_event = Some(Mutex::new(Box::new(on_event)));
+        self
+    }
+
+    /// Encrypt the environment's log and region files, and any database opened with
+    /// `DatabaseBuilder::encrypted`, with AES using a key derived from `passphrase`.
+    ///
+    /// Every process opening the environment must give the same passphrase.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .encrypt("correct horse battery staple")
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .encrypted()
+    ///     .open()
+    ///     .unwrap();
+    /// db.put(None, b"key", b"secret", libdb::DB_NONE).unwrap();
+    /// # }
+    /// ```
+    pub fn encrypt(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(CString::new(passphrase).unwrap());
+        self
+    }
+
+    /// Allocate `count` mutexes beyond those libdb calculates it needs, for applications
+    /// which create many database handles or cursors while the environment is open.
+    pub fn mutex_increment(mut self, count: u32) -> Self {
+        self.mutex_increment = Some(count);
+        self
+    }
+
+    /// Set how many lockers (transactions and non-transactional cursors or handles) the
+    /// lock table has room for.
+    ///
+    /// The default lock table sizes are soon exhausted by large transactions, which then
+    /// fail with `ENOMEM`.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .max_lockers(2000)
+    ///     .max_locks(100000)
+    ///     .max_lock_objects(100000)
+    ///     .open()
+    ///     .unwrap();
+    /// let stat = env.lock_stat(libdb::DB_NONE).unwrap();
+    /// assert_eq!(100000, stat.max_locks);
+    /// # }
+    /// ```
+    pub fn max_lockers(mut self, count: u32) -> Self {
+        self.lk_max_lockers = Some(count);
+        self
+    }
+
+    /// Set how many locks the lock table has room for.
+    pub fn max_locks(mut self, count: u32) -> Self {
+        self.lk_max_locks = Some(count);
+        self
+    }
+
+    /// Set how many objects (such as pages) the lock table can hold locks on at once.
+    pub fn max_lock_objects(mut self, count: u32) -> Self {
+        self.lk_max_objects = Some(count);
+        self
+    }
+
+    /// Split the lock table into `count` partitions, each with its own mutex.
+    ///
+    /// Threads locking objects in different partitions do not contend with one another,
+    /// which helps on machines with many cores. libdb defaults to ten partitions per CPU.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .lock_partitions(64)
+    ///     .open()
+    ///     .unwrap();
+    /// assert_eq!(64, env.lock_stat(libdb::DB_NONE).unwrap().partitions);
+    /// # }
+    /// ```
+    pub fn lock_partitions(mut self, count: u32) -> Self {
+        self.lk_partitions = Some(count);
+        self
+    }
+
+    /// Allocate room for `count` objects of the given kind when the environment's shared
+    /// regions are created.
+    ///
+    /// Objects beyond the initial allocation are added as needed, up to the limit set by
+    /// `memory_max`; sizing the regions up front avoids growing them under load. Call once
+    /// for each kind of object to size.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .memory_init(libdb::RegionObject::Lock, 10000)
+    ///     .memory_init(libdb::RegionObject::Transaction, 500)
+    ///     .memory_max(0, 512 * 1024 * 1024)
+    ///     .open()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "v5_3")]
+    pub fn memory_init(mut self, object: RegionObject, count: u32) -> Self {
+        self.memory_init.push((object, count));
+        self
+    }
+
+    /// Cap the memory of the environment's shared regions, excluding the cache, at `gbytes`
+    /// gigabytes plus `bytes` bytes.
+    #[cfg(feature = "v5_3")]
+    pub fn memory_max(mut self, gbytes: u32, bytes: u32) -> Self {
+        self.memory_max = Some((gbytes, bytes));
+        self
+    }
+
     /// Open the Berkeley DB Environment.
     pub fn open(mut self) -> Result<Environment, Error> {
+        let flags = self.flags;
+        self.open_with(flags)
+    }
+
+    /// Open the environment registered with `DB_REGISTER`, running recovery first if a
+    /// process died while using it.
+    ///
+    /// Every process sharing the environment should open it this way. The first attempt
+    /// joins without recovery; if libdb reports another process failed while holding the
+    /// environment (`DB_RUNRECOVERY`), it is opened again with `DB_RECOVER`. Add
+    /// `DB_FAILCHK` to the flags, with `thread_count` and `is_alive`, to also clean up
+    /// after threads which died without corrupting the environment.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open_or_recover()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    pub fn open_or_recover(mut self) -> Result<Environment, Error> {
+        let flags = self.flags | DB_REGISTER;
+        match self.open_with(flags) {
+            Err(ref e) if e.errno() == error::DB_RUNRECOVERY => (),
+            ret => return ret,
+        }
+        // A handle which failed to open cannot be opened again.
+        unsafe {
+            ffi_call!(self.env_ptr, close, 0);
+            self.env_ptr = ptr::null_mut();
+            check(ffi_fn!(db_env_create, &mut self.env_ptr, 0))?;
+            (*self.env_ptr).app_private = &mut *self.ctx as *mut EnvContext as *mut c_void;
+        }
+        self.open_with(flags | DB_RECOVER)
+    }
+
+    /// Configure the handle and open it with `flags`.
+    fn open_with(&mut self, flags: Flags) -> Result<Environment, Error> {
         // Get a pointer to the home directory.
         let home_ptr = match self.home.as_ref() {
             Some(cstr) => cstr.as_ptr(),
@@ -69,10 +764,107 @@ impl EnvironmentBuilder {
         };
 
         unsafe {
-            match ((*self.env_ptr).open.unwrap())(self.env_ptr, home_ptr, self.flags.bits(), self.mode) {
+            if let Some(timestamp) = self.tx_timestamp {
+                let since = timestamp.duration_since(UNIX_EPOCH).map_err(|_| Error::new(libc::EINVAL))?;
+                let mut secs = since.as_secs() as libc::time_t;
+                check(ffi_call!(self.env_ptr, set_tx_timestamp, &mut secs))?;
+            }
+            if let Some((gbytes, bytes, ncache)) = self.cache_size {
+                check(ffi_call!(self.env_ptr, set_cachesize, gbytes, bytes, ncache))?;
+            }
+            if let Some(bytes) = self.mp_mmapsize {
+                check(ffi_call!(self.env_ptr, set_mp_mmapsize, bytes))?;
+            }
+            if let Some(count) = self.mp_max_openfd {
+                check(ffi_call!(self.env_ptr, set_mp_max_openfd, count as i32))?;
+            }
+            if let Some((max_write, sleep)) = self.mp_max_write {
+                let max_write = i32::try_from(max_write).map_err(|_| Error::new(libc::EINVAL))?;
+                check(ffi_call!(self.env_ptr, set_mp_max_write, max_write, timeout_micros(sleep)))?;
+            }
+            if let Some(policy) = self.lk_detect {
+                check(ffi_call!(self.env_ptr, set_lk_detect, policy as u32))?;
+            }
+            if let Some(count) = self.lk_max_lockers {
+                check(ffi_call!(self.env_ptr, set_lk_max_lockers, count))?;
+            }
+            if let Some(count) = self.lk_max_locks {
+                check(ffi_call!(self.env_ptr, set_lk_max_locks, count))?;
+            }
+            if let Some(count) = self.lk_max_objects {
+                check(ffi_call!(self.env_ptr, set_lk_max_objects, count))?;
+            }
+            if let Some(count) = self.lk_partitions {
+                check(ffi_call!(self.env_ptr, set_lk_partitions, count))?;
+            }
+            #[cfg(feature = "v5_3")]
+            {
+                for &(object, count) in &self.memory_init {
+                    check(ffi_call!(self.env_ptr, set_memory_init, db_ffi::DB_MEM_CONFIG::from(object), count))?;
+                }
+                if let Some((gbytes, bytes)) = self.memory_max {
+                    check(ffi_call!(self.env_ptr, set_memory_max, gbytes, bytes))?;
+                }
+            }
+            if let Some(bytes) = self.lg_bsize {
+                check(ffi_call!(self.env_ptr, set_lg_bsize, bytes))?;
+            }
+            if let Some(bytes) = self.lg_max {
+                check(ffi_call!(self.env_ptr, set_lg_max, bytes))?;
+            }
+            if let Some(ref dir) = self.lg_dir {
+                check(ffi_call!(self.env_ptr, set_lg_dir, dir.as_ptr()))?;
+            }
+            for dir in &self.data_dirs {
+                check(ffi_call!(self.env_ptr, add_data_dir, dir.as_ptr()))?;
+            }
+            if let Some(ref dir) = self.tmp_dir {
+                check(ffi_call!(self.env_ptr, set_tmp_dir, dir.as_ptr()))?;
+            }
+            if let Some(ref dir) = self.create_dir {
+                check(ffi_call!(self.env_ptr, set_create_dir, dir.as_ptr()))?;
+            }
+            if let Some(ref mode) = self.dir_mode {
+                check(ffi_call!(self.env_ptr, set_intermediate_dir_mode, mode.as_ptr()))?;
+            }
+            if let Some(key) = self.shm_key {
+                let key = c_long::try_from(key).map_err(|_| Error::new(libc::EINVAL))?;
+                check(ffi_call!(self.env_ptr, set_shm_key, key))?;
+            }
+            if let Some(count) = self.thread_count {
+                check(ffi_call!(self.env_ptr, set_thread_count, count))?;
+            }
+            if let Some(count) = self.mutex_increment {
+                check(ffi_call!(self.env_ptr, mutex_set_increment, count))?;
+            }
+            if let Some(ref passphrase) = self.passphrase {
+                check(ffi_call!(self.env_ptr, set_encrypt, passphrase.as_ptr(), DB_ENCRYPT_AES.bits()))?;
+            }
+            if self.ctx.is_alive.is_some() {
+                check(ffi_call!(self.env_ptr, set_isalive, Some(is_alive_callback)))?;
+            }
+            if self.ctx.thread_id.is_some() {
+                check(ffi_call!(self.env_ptr, set_thread_id, Some(thread_id_callback)))?;
+            }
+            if self.ctx.on_error.is_some() {
+                ffi_call!(self.env_ptr, set_errcall, Some(error_callback));
+            }
+            if self.ctx.on_message.is_some() {
+                ffi_call!(self.env_ptr, set_msgcall, Some(message_callback));
+            }
+            // Installed even without `on_event`, to learn when the environment panics.
+            check(ffi_call!(self.env_ptr, set_event_notify, Some(event_callback)))?;
+            match ffi_call!(self.env_ptr, open, home_ptr, flags.bits(), self.mode) {
                 0 => {
                     let env = Env {
                         env_ptr: self.env_ptr,
+                        read_only: self.read_only,
+                        concurrent_data_store: flags.contains(DB_INIT_CDB),
+                        locking: flags.intersects(DB_INIT_LOCK | DB_INIT_CDB),
+                        resources: Arc::new(Resources::default()),
+                        subdbs: Mutex::new(HashMap::new()),
+                        services: ServiceSet::new(),
+                        ctx: mem::replace(&mut self.ctx, Box::new(EnvContext::new())),
                     };
                     self.env_ptr = ptr::null_mut();
                     Ok(Arc::new(env))
@@ -87,7 +879,7 @@ impl Drop for EnvironmentBuilder {
     fn drop(&mut self) {
         if ptr::null() != self.env_ptr {
             unsafe {
-                ((*self.env_ptr).close.unwrap())(self.env_ptr, 0);
+                ffi_call!(self.env_ptr, close, 0);
             }
         }
     }
@@ -104,294 +896,4890 @@ impl Drop for EnvironmentBuilder {
 /// ```
 pub struct Env {
     env_ptr: *mut db_ffi::DB_ENV,
+    read_only: bool,
+    // Opened with DB_INIT_CDB, so writing cursors need DB_WRITECURSOR.
+    concurrent_data_store: bool,
+    // Opened with DB_INIT_LOCK or DB_INIT_CDB, so reads may take write locks with DB_RMW.
+    locking: bool,
+    resources: Arc<Resources>,
+    // Handles opened by `open_subdb`. Weak, since each database holds the environment.
+    subdbs: Mutex<HashMap<(String, String), Weak<Db>>>,
+    services: ServiceSet,
+    // Referenced by libdb through DB_ENV->app_private; must outlive the DB_ENV handle.
+    ctx: Box<EnvContext>,
 }
 
-impl Env {
-    /// Begin a new transaction in the environment.
-    pub fn txn(&self, parent: Option<&Transaction>, flags: Flags) -> Result<Transaction, Error> {
-        unsafe {
-            let mut txn_ptr: *mut db_ffi::DB_TXN = ptr::null_mut();
-            let ret = ((*self.env_ptr).txn_begin.unwrap())(self.env_ptr, unwrap_txn_ptr(parent), &mut txn_ptr, flags.bits());
-            match ret {
-                0 => Ok(Transaction { txn_ptr: txn_ptr }),
-                e => Err(Error::new(e)),
-            }
-        }
-    }
+/// Counts of the handles open in an environment, shared with its transactions.
+#[derive(Default)]
+struct Resources {
+    databases: AtomicUsize,
+    cursors: AtomicUsize,
+    transactions: AtomicUsize,
+    // An environment dropped while transactions were outstanding, left for the last of
+    // them to close.
+    orphan: Mutex<Option<OrphanEnv>>,
 }
 
-impl Drop for Env {
-    fn drop(&mut self) {
-        if ptr::null() != self.env_ptr {
-            unsafe {
-                ((*self.env_ptr).close.unwrap())(self.env_ptr, 0);
+impl Resources {
+    /// Count a finished transaction, closing an orphaned environment after the last.
+    fn transaction_finished(&self) {
+        if self.transactions.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let orphan = self.orphan.lock().unwrap_or_else(|e| e.into_inner()).take();
+            if let Some(orphan) = orphan {
+                unsafe {
+                    ffi_call!(orphan.env_ptr, close, 0);
+                }
             }
         }
     }
 }
 
-pub enum DbType {
-    BTree,
-    Hash,
-    Recno,
-    Queue,
-    Any,
+/// An environment handle awaiting close, with the context libdb calls back into.
+struct OrphanEnv {
+    env_ptr: *mut db_ffi::DB_ENV,
+    // Referenced by libdb through DB_ENV->app_private until the handle is closed.
+    _ctx: Box<EnvContext>,
 }
 
-impl From<DbType> for db_ffi::DBTYPE {
-    fn from(flavor: DbType) -> Self {
-        match flavor {
-            DbType::BTree => db_ffi::DBTYPE::DB_BTREE,
-            DbType::Hash => db_ffi::DBTYPE::DB_HASH,
-            DbType::Recno => db_ffi::DBTYPE::DB_RECNO,
-            DbType::Queue => db_ffi::DBTYPE::DB_QUEUE,
-            DbType::Any => db_ffi::DBTYPE::DB_UNKNOWN,
+unsafe impl Send for OrphanEnv {}
+
+/// The handles open in an environment, as returned by `Env::resource_usage`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// Open database handles.
+    pub databases: usize,
+    /// Open cursors.
+    pub cursors: usize,
+    /// Transactions which have not been committed or aborted.
+    pub transactions: usize,
+}
+
+impl Env {
+    /// Is the environment read-only?
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Return the environment's home directory, if it has one.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// use std::time::Duration;
+    ///
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_MPOOL)
+    ///     .cache_size(0, 32 * 1024 * 1024, 1)
+    ///     .open()
+    ///     .unwrap();
+    /// env.apply_config(&libdb::EnvTuning::new().lock_timeout(Duration::from_millis(500))).unwrap();
+    ///
+    /// assert_eq!(Some(dir.path().to_path_buf()), env.home().unwrap());
+    /// assert!(env.open_flags().unwrap().contains(libdb::DB_INIT_LOCK));
+    /// assert!(env.cache_size().unwrap().1 >= 32 * 1024 * 1024);
+    /// assert_eq!(Duration::from_millis(500), env.timeout(libdb::TimeoutKind::Lock).unwrap());
+    /// # }
+    /// ```
+    pub fn home(&self) -> Result<Option<PathBuf>, Error> {
+        unsafe {
+            let mut home: *const c_char = ptr::null();
+            check(ffi_call!(self.env_ptr, get_home, &mut home))?;
+            Ok(cstr_to_string(home).map(PathBuf::from))
+        }
+    }
+
+    /// Return the flags the environment was opened with.
+    pub fn open_flags(&self) -> Result<Flags, Error> {
+        unsafe {
+            let mut flags = 0;
+            check(ffi_call!(self.env_ptr, get_open_flags, &mut flags))?;
+            Ok(Flags::from_bits_truncate(flags))
+        }
+    }
+
+    /// Return the flags turned on with `set_flags`.
+    pub fn flags(&self) -> Result<Flags, Error> {
+        unsafe {
+            let mut flags = 0;
+            check(ffi_call!(self.env_ptr, get_flags, &mut flags))?;
+            Ok(Flags::from_bits_truncate(flags))
+        }
+    }
+
+    /// Return the size of the cache as gigabytes, bytes and the number of regions it is
+    /// split into, as given to `EnvironmentBuilder::cache_size`.
+    pub fn cache_size(&self) -> Result<(u32, u32, i32), Error> {
+        let mut gbytes = 0;
+        let mut bytes = 0;
+        let mut ncache = 0;
+        unsafe {
+            check(ffi_call!(self.env_ptr, get_cachesize, &mut gbytes, &mut bytes, &mut ncache))?;
+        }
+        Ok((gbytes, bytes, ncache))
+    }
+
+    /// Return the environment's lock or transaction timeout, which is zero if there is none.
+    pub fn timeout(&self, kind: TimeoutKind) -> Result<Duration, Error> {
+        let which = match kind {
+            TimeoutKind::Lock => DB_SET_LOCK_TIMEOUT,
+            TimeoutKind::Txn  => DB_SET_TXN_TIMEOUT,
+        };
+        let mut timeout = 0;
+        unsafe {
+            check(ffi_call!(self.env_ptr, get_timeout, &mut timeout, which.bits()))?;
+        }
+        Ok(Duration::from_micros(timeout as u64))
+    }
+
+    /// Return the directory log files are kept in, if it is not the home directory.
+    pub fn log_dir(&self) -> Result<Option<PathBuf>, Error> {
+        unsafe {
+            let mut dir: *const c_char = ptr::null();
+            check(ffi_call!(self.env_ptr, get_lg_dir, &mut dir))?;
+            Ok(cstr_to_string(dir).map(PathBuf::from))
+        }
+    }
+
+    /// Return the directories searched for database files.
+    pub fn data_dirs(&self) -> Result<Vec<PathBuf>, Error> {
+        unsafe {
+            let mut dirs: *mut *const c_char = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, get_data_dirs, &mut dirs))?;
+            let mut paths = Vec::new();
+            if !dirs.is_null() {
+                // The list belongs to the environment, and is terminated by a null pointer.
+                let mut entry = dirs;
+                while !(*entry).is_null() {
+                    paths.push(PathBuf::from(CStr::from_ptr(*entry).to_string_lossy().into_owned()));
+                    entry = entry.offset(1);
+                }
+            }
+            Ok(paths)
+        }
+    }
+
+    /// Return the environment's handle generation.
+    ///
+    /// Database handles remember the generation they were opened in and refuse to operate
+    /// once it has moved on. It moves on when libdb reports that the environment has
+    /// failed and must be recovered, whether by an operation returning `DB_RUNRECOVERY` or
+    /// by a `DB_EVENT_PANIC` event (which is also how recovery by another process shows
+    /// up), and when `invalidate_handles` is called.
+    pub fn generation(&self) -> usize {
+        self.ctx.generation.load(Ordering::SeqCst)
+    }
+
+    /// Invalidate every database handle opened in the current generation.
+    ///
+    /// Handles are invalidated automatically when libdb reports that the environment needs
+    /// recovery; call this when recovery is known to have happened some other way.
+    /// Operations on previously opened handles then fail with `ErrorKind::HandleInvalidated`
+    /// instead of touching freed library state, and the databases must be reopened.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// env.invalidate_handles();
+    ///
+    /// let key = String::from("key").into_bytes();
+    /// let err = db.get(None, key.as_slice(), libdb::DB_NONE).unwrap_err();
+    /// assert_eq!(libdb::ErrorKind::HandleInvalidated, err.kind());
+    /// ```
+    pub fn invalidate_handles(&self) {
+        self.ctx.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Check for threads of control which exited while using the environment, and release
+    /// the locks and transactions they left behind.
+    ///
+    /// Requires `EnvironmentBuilder::thread_count` and `is_alive`, and fails with `EINVAL`
+    /// without them. A watchdog can run this periodically, or whenever a process sharing
+    /// the environment exits, rather than stopping every process to run recovery. If a dead
+    /// thread was modifying shared state, it fails with `DB_RUNRECOVERY`; every database
+    /// handle is then invalidated, as by `invalidate_handles`, and the environment must be
+    /// recovered.
+    pub fn failchk(&self) -> Result<(), Error> {
+        if self.ctx.is_alive.is_none() {
+            return Err(Error::new(libc::EINVAL));
+        }
+        unsafe {
+            match ffi_call!(self.env_ptr, failchk, 0) {
+                0 => Ok(()),
+                error::DB_RUNRECOVERY => {
+                    self.invalidate_handles();
+                    Err(Error::new(error::DB_RUNRECOVERY))
+                },
+                e => Err(Error::new(e)),
+            }
+        }
+    }
+
+    /// Return the number of database handles, cursors and transactions open in the
+    /// environment.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// let cursor = db.cursor(Some(&txn), libdb::DB_NONE).unwrap();
+    ///
+    /// let usage = env.resource_usage();
+    /// assert_eq!((1, 1, 1), (usage.databases, usage.cursors, usage.transactions));
+    ///
+    /// drop(cursor);
+    /// txn.commit(libdb::CommitType::Inherit).unwrap();
+    /// assert_eq!(0, env.resource_usage().transactions);
+    /// ```
+    pub fn resource_usage(&self) -> ResourceUsage {
+        ResourceUsage {
+            databases: self.resources.databases.load(Ordering::SeqCst),
+            cursors: self.resources.cursors.load(Ordering::SeqCst),
+            transactions: self.resources.transactions.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Close the environment.
+    ///
+    /// Fails with `ErrorKind::HandlesOutstanding` if the environment is shared with other
+    /// owners, such as open database handles, or transactions begun in it are still open.
+    /// A shared environment is closed when its last owner drops it; one with outstanding
+    /// transactions is closed when the last of them finishes, so they can still be aborted
+    /// safely.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    ///
+    /// let shared = env.clone();
+    /// let err = env.close().unwrap_err();
+    /// assert_eq!(libdb::ErrorKind::HandlesOutstanding, err.kind());
+    ///
+    /// txn.abort().unwrap();
+    /// assert!(shared.close().is_ok());
+    /// ```
+    pub fn close(self: Arc<Self>) -> Result<(), Error> {
+        if self.resources.transactions.load(Ordering::SeqCst) > 0 {
+            return Err(Error::from_kind(ErrorKind::HandlesOutstanding));
+        }
+        let mut env = match Arc::try_unwrap(self) {
+            Ok(env) => env,
+            Err(_)  => return Err(Error::from_kind(ErrorKind::HandlesOutstanding)),
+        };
+        env.services.stop();
+        unsafe {
+            let ret = ffi_call!(env.env_ptr, close, 0);
+            env.env_ptr = ptr::null_mut();
+            check(ret)
+        }
+    }
+
+    /// Apply the settings in `tuning` to the open environment.
+    ///
+    /// Only the settings which were given are changed, so a long-running service can reload
+    /// them from its configuration without reopening the environment. Settings are applied
+    /// in order and the first failure is returned, leaving the settings before it applied.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let tuning = libdb::EnvTuning::new()
+    ///     .lock_timeout(Duration::from_millis(500))
+    ///     .txn_timeout(Duration::from_secs(10))
+    ///     .verbose(libdb::DB_VERB_DEADLOCK, true)
+    ///     .cache_size(64 * 1024 * 1024);
+    /// assert!(env.apply_config(&tuning).is_ok());
+    /// ```
+    pub fn apply_config(&self, tuning: &EnvTuning) -> Result<(), Error> {
+        unsafe {
+            if let Some(timeout) = tuning.lock_timeout {
+                check(ffi_call!(self.env_ptr, set_timeout, timeout, DB_SET_LOCK_TIMEOUT.bits()))?;
+            }
+            if let Some(timeout) = tuning.txn_timeout {
+                check(ffi_call!(self.env_ptr, set_timeout, timeout, DB_SET_TXN_TIMEOUT.bits()))?;
+            }
+            for &(which, on) in &tuning.verbose {
+                check(ffi_call!(self.env_ptr, set_verbose, which.bits(), on as i32))?;
+            }
+            if let Some(bytes) = tuning.cache_size {
+                let gbytes = (bytes / GIGABYTE) as u32;
+                let bytes = (bytes % GIGABYTE) as u32;
+                check(ffi_call!(self.env_ptr, set_cachesize, gbytes, bytes, 0))?;
+            }
+            if let Some(spins) = tuning.mutex_tas_spins {
+                check(ffi_call!(self.env_ptr, mutex_set_tas_spins, spins))?;
+            }
+        }
+        for &(ref name, interval) in &tuning.service_intervals {
+            self.services.set_interval(name, interval)?;
+        }
+        Ok(())
+    }
+
+    /// Return the policy the deadlock detector runs with on every conflict, if it does.
+    pub fn deadlock_policy(&self) -> Result<Option<DeadlockPolicy>, Error> {
+        let mut detect: u32 = 0;
+        unsafe {
+            check(ffi_call!(self.env_ptr, get_lk_detect, &mut detect))?;
+        }
+        Ok(DeadlockPolicy::from_raw(detect))
+    }
+
+    /// Run the deadlock detector once, rejecting requests chosen by `policy` to break any
+    /// deadlocks, and return how many lock requests were rejected.
+    ///
+    /// Applications which do not set `EnvironmentBuilder::deadlock_policy` can run this
+    /// periodically, e.g. from a background service.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// assert_eq!(0, env.lock_detect(libdb::DeadlockPolicy::Default).unwrap());
+    /// # }
+    /// ```
+    pub fn lock_detect(&self, policy: DeadlockPolicy) -> Result<u32, Error> {
+        let mut rejected: i32 = 0;
+        unsafe {
+            check(ffi_call!(self.env_ptr, lock_detect, 0, policy as u32, &mut rejected))?;
+        }
+        Ok(rejected as u32)
+    }
+
+    /// Turn the environment flags in `flags` on or off while the environment is open.
+    ///
+    /// Flags such as `DB_TXN_NOSYNC`, `DB_TXN_WRITE_NOSYNC`, `DB_AUTO_COMMIT` and
+    /// `DB_DIRECT_DB` can be changed this way, e.g. to relax durability during a bulk load.
+    /// The change applies to this handle only, not to other processes sharing the
+    /// environment.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// env.set_flags(libdb::DB_TXN_NOSYNC, true).unwrap();
+    /// // ... bulk load ...
+    /// env.set_flags(libdb::DB_TXN_NOSYNC, false).unwrap();
+    /// ```
+    pub fn set_flags(&self, flags: Flags, on: bool) -> Result<(), Error> {
+        unsafe {
+            check(ffi_call!(self.env_ptr, set_flags, flags.bits(), on as i32))
+        }
+    }
+
+    /// Limit how many dirty pages the cache writes at a time, sleeping for `sleep` between
+    /// batches.
+    ///
+    /// Evicting old page versions kept for snapshot transactions can cause bursts of
+    /// writes; limiting them keeps the disk responsive for other work. A `max_write` of 0
+    /// removes the limit. Fails with `EINVAL` if `max_write` exceeds `i32::MAX`, the most
+    /// libdb accepts.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// env.set_mp_max_write(16, Duration::from_millis(5)).unwrap();
+    /// assert_eq!((16, Duration::from_millis(5)), env.mp_max_write().unwrap());
+    /// ```
+    pub fn set_mp_max_write(&self, max_write: u32, sleep: Duration) -> Result<(), Error> {
+        let max_write = i32::try_from(max_write).map_err(|_| Error::new(libc::EINVAL))?;
+        unsafe {
+            check(ffi_call!(self.env_ptr, set_mp_max_write, max_write, timeout_micros(sleep)))
+        }
+    }
+
+    /// Return the limit set by `set_mp_max_write`.
+    pub fn mp_max_write(&self) -> Result<(u32, Duration), Error> {
+        let mut max_write = 0;
+        let mut sleep = 0;
+        unsafe {
+            check(ffi_call!(self.env_ptr, get_mp_max_write, &mut max_write, &mut sleep))?;
+        }
+        // libdb only stores limits set through `set_mp_max_write`, which are not negative.
+        Ok((max_write.max(0) as u32, Duration::from_micros(sleep as u64)))
+    }
+
+    /// Open the database `name` within `file`, or return the handle already open for it.
+    ///
+    /// Handles are cached by file and name for as long as any clone of them is alive, so an
+    /// application can ask for a subdatabase wherever it needs it without opening it again.
+    /// `config` is used only when the database is not already open; its environment, file
+    /// and name are replaced.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # use std::sync::Arc;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let config = || libdb::DatabaseBuilder::new().flags(libdb::DB_CREATE);
+    /// let users = env.open_subdb("app.db", "users", config()).unwrap();
+    /// let groups = env.open_subdb("app.db", "groups", config()).unwrap();
+    /// assert!(!Arc::ptr_eq(&users, &groups));
+    ///
+    /// let again = env.open_subdb("app.db", "users", config()).unwrap();
+    /// assert!(Arc::ptr_eq(&users, &again));
+    /// # }
+    /// ```
+    pub fn open_subdb(self: &Arc<Self>, file: &str, name: &str, config: DatabaseBuilder) -> Result<Database, Error> {
+        let key = (file.to_string(), name.to_string());
+        if let Some(db) = self.lock_subdbs()?.get(&key).and_then(|db| db.upgrade()) {
+            return Ok(db);
+        }
+
+        // The registry is not locked while opening, so other threads are not held up.
+        let db = config.environment(self).file(file).name(name).open()?;
+
+        let mut subdbs = self.lock_subdbs()?;
+        // Another thread may have opened the database meanwhile; keep its handle.
+        if let Some(db) = subdbs.get(&key).and_then(|db| db.upgrade()) {
+            return Ok(db);
+        }
+        // Forget handles which have since been closed, so the cache does not grow.
+        subdbs.retain(|_, db| db.strong_count() > 0);
+        subdbs.insert(key, Arc::downgrade(&db));
+        Ok(db)
+    }
+
+    /// Lock the `open_subdb` registry.
+    ///
+    /// Fails with `ENOTRECOVERABLE` if a thread panicked while holding the lock.
+    fn lock_subdbs(&self) -> Result<MutexGuard<'_, HashMap<(String, String), Weak<Db>>>, Error> {
+        self.subdbs.lock().map_err(|_| Error::new(libc::ENOTRECOVERABLE))
+    }
+
+    /// Remove the database `name` in `file`, or the whole file if `name` is `None`.
+    ///
+    /// Given a transaction, or `DB_AUTO_COMMIT`, the removal is transactional, so it can be
+    /// committed or aborted along with other work. The database must not be open.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let config = || libdb::DatabaseBuilder::new().flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT);
+    /// drop(env.open_subdb("app.db", "staging", config()).unwrap());
+    ///
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// env.dbrename(Some(&txn), "app.db", Some("staging"), "live", libdb::DB_NONE).unwrap();
+    /// txn.commit(libdb::CommitType::Inherit).unwrap();
+    ///
+    /// env.dbremove(None, "app.db", Some("live"), libdb::DB_AUTO_COMMIT).unwrap();
+    /// # }
+    /// ```
+    pub fn dbremove(&self, txn: Option<&Transaction>, file: &str, name: Option<&str>, flags: Flags) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::from_kind(ErrorKind::ReadOnly));
+        }
+        let file = CString::new(file).unwrap();
+        let name = name.map(|name| CString::new(name).unwrap());
+        let name_ptr = name.as_ref().map_or(ptr::null(), |name| name.as_ptr());
+        unsafe {
+            check(ffi_call!(self.env_ptr, dbremove, unwrap_txn_ptr(txn), file.as_ptr(), name_ptr, flags.bits()))
+        }
+    }
+
+    /// Rename the database `name` in `file` to `new_name`, or rename the whole file to
+    /// `new_name` if `name` is `None`.
+    ///
+    /// Given a transaction, or `DB_AUTO_COMMIT`, the rename is transactional, so it can be
+    /// committed or aborted along with other work. The database must not be open.
+    pub fn dbrename(&self, txn: Option<&Transaction>, file: &str, name: Option<&str>, new_name: &str, flags: Flags) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::from_kind(ErrorKind::ReadOnly));
+        }
+        let file = CString::new(file).unwrap();
+        let name = name.map(|name| CString::new(name).unwrap());
+        let name_ptr = name.as_ref().map_or(ptr::null(), |name| name.as_ptr());
+        let new_name = CString::new(new_name).unwrap();
+        unsafe {
+            check(ffi_call!(self.env_ptr, dbrename, unwrap_txn_ptr(txn), file.as_ptr(), name_ptr, new_name.as_ptr(), flags.bits()))
+        }
+    }
+
+    /// Start a background service which runs `task` every `interval`.
+    ///
+    /// The service holds the environment only while `task` runs, so it never keeps the
+    /// environment open. When the environment is shut down the task runs one last time, which
+    /// lets a checkpointer write a final checkpoint or a sampler record its last sample. A task
+    /// which fails stops its service, and the error is returned by `shutdown`.
+    ///
+    /// Fails with `EINVAL` once the environment has been shut down.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let runs = Arc::new(AtomicUsize::new(0));
+    /// let counter = runs.clone();
+    /// env.spawn_service("sampler", Duration::from_secs(60), move |_env| {
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    ///     Ok(())
+    /// }).unwrap();
+    ///
+    /// env.shutdown(Duration::from_secs(5)).unwrap();
+    /// assert_eq!(1, runs.load(Ordering::SeqCst));
+    /// ```
+    pub fn spawn_service<F>(self: &Arc<Self>, name: &str, interval: Duration, task: F) -> Result<(), Error>
+        where F: FnMut(&Env) -> Result<(), Error> + Send + 'static
+    {
+        self.services.spawn(Arc::downgrade(self), name, interval, task)
+    }
+
+    /// Stop the environment's background services, waiting up to `timeout` for them to
+    /// finish their final run.
+    ///
+    /// Call this before closing the environment, so that its services do not race the close.
+    /// Returns the first error a service failed with, or `ErrorKind::Timeout` if any are
+    /// still running at the deadline. Dropping or closing the environment only signals its
+    /// services to stop, without waiting for them or letting them run again.
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), Error> {
+        self.services.shutdown(timeout)
+    }
+
+    /// Run `body` in a new transaction, committing it if `body` succeeds and aborting it if
+    /// `body` fails or panics.
+    ///
+    /// When the transaction is chosen as a deadlock victim (`DB_LOCK_DEADLOCK`), it is
+    /// aborted and `body` is run again in a new transaction, up to `retries` more times.
+    /// `body` must therefore be safe to repeat. When `body` fails, its error is returned even
+    /// if aborting the transaction fails too.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let old = env.with_txn(libdb::TxnFlags::new(), 3, |txn| {
+    ///     let old = db.get(Some(txn), b"counter", libdb::DB_RMW)?.map(|v| v.to_vec());
+    ///     db.put(Some(txn), b"counter", b"1", libdb::DB_NONE)?;
+    ///     Ok(old)
+    /// }).unwrap();
+    /// assert_eq!(None, old);
+    /// ```
+    pub fn with_txn<T, F>(&self, flags: TxnFlags, retries: u32, mut body: F) -> Result<T, Error>
+        where F: FnMut(&Transaction) -> Result<T, Error>
+    {
+        let mut attempt = 0;
+        loop {
+            let txn = self.txn(None, flags)?;
+            match body(&txn) {
+                Ok(value) => {
+                    txn.commit(CommitType::Inherit)?;
+                    return Ok(value);
+                },
+                Err(e) => {
+                    // Report the body's error rather than any failure to abort, and only
+                    // retry once the failed attempt is fully rolled back.
+                    let aborted = txn.abort().is_ok();
+                    if !aborted || e.kind() != ErrorKind::Db || e.errno() != error::DB_LOCK_DEADLOCK || attempt >= retries {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                },
+            }
+        }
+    }
+
+    /// Return the transactions which were prepared but not resolved before the
+    /// environment was last closed, with their global transaction IDs.
+    ///
+    /// Run this after opening the environment with `DB_RECOVER`. A two-phase commit
+    /// coordinator then commits or aborts each transaction, or discards those it leaves
+    /// for another process to resolve; any it drops are aborted.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let flags = libdb::DB_CREATE | libdb::DB_RECOVER | libdb::DB_INIT_LOCK |
+    ///             libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN;
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(flags)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// for (txn, gid) in env.txn_recover().unwrap() {
+    ///     if gid[0] == 1 {
+    ///         txn.commit(libdb::CommitType::Inherit).unwrap();
+    ///     } else {
+    ///         txn.abort().unwrap();
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn txn_recover(&self) -> Result<Vec<(Transaction, [u8; GID_SIZE])>, Error> {
+        const BATCH: usize = 16;
+        let mut recovered = Vec::new();
+        let mut flags = db_ffi::DB_FIRST;
+        loop {
+            let mut list: [db_ffi::DB_PREPLIST; BATCH] = unsafe { mem::zeroed() };
+            let mut count = 0;
+            unsafe {
+                check(ffi_call!(self.env_ptr, txn_recover, list.as_mut_ptr(), BATCH as c_long, &mut count, flags))?;
+            }
+            if count == 0 {
+                return Ok(recovered);
+            }
+            for prepared in &list[..count as usize] {
+                self.resources.transactions.fetch_add(1, Ordering::SeqCst);
+                let txn = Transaction {
+                    txn_ptr: prepared.txn,
+                    env_ptr: self.env_ptr,
+                    resources: self.resources.clone(),
+                    cds_group: false,
+                };
+                recovered.push((txn, prepared.gid));
+            }
+            flags = db_ffi::DB_NEXT;
+        }
+    }
+
+    /// Begin a group of operations in a Concurrent Data Store environment.
+    ///
+    /// The group is passed wherever a transaction is accepted. Its operations share the
+    /// group's locks, so a cursor write and other operations in the same group do not
+    /// block each other. Groups are not atomic: operations are never undone, and the group
+    /// cannot be aborted. End it with `commit`, or by dropping it; either releases its
+    /// locks.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_CDB | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let group = env.cdsgroup_begin().unwrap();
+    /// {
+    ///     let mut cursor = db.write_cursor(Some(&group), libdb::DB_NONE).unwrap();
+    ///     cursor.put(b"a", b"1").unwrap();
+    ///     db.put(Some(&group), b"b", b"2", libdb::DB_NONE).unwrap();
+    /// }
+    /// group.commit().unwrap();
+    /// ```
+    pub fn cdsgroup_begin(&self) -> Result<CdsGroup, Error> {
+        unsafe {
+            let mut txn_ptr: *mut db_ffi::DB_TXN = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, cdsgroup_begin, &mut txn_ptr))?;
+            self.resources.transactions.fetch_add(1, Ordering::SeqCst);
+            let txn = Transaction {
+                txn_ptr: txn_ptr,
+                env_ptr: self.env_ptr,
+                resources: self.resources.clone(),
+                cds_group: true,
+            };
+            Ok(CdsGroup { txn: txn })
+        }
+    }
+
+    /// Write a checkpoint, flushing the cache and bounding how much of the log recovery
+    /// must replay.
+    ///
+    /// The checkpoint is skipped unless at least `kbytes` of log have been written or
+    /// `minutes` have passed since the last one; with both 0, or with `DB_FORCE`, it is
+    /// always written.
+    ///
+    /// # Examples
+    /// Checkpoint every minute from a background service, with a final checkpoint at
+    /// shutdown:
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// env.spawn_service("checkpoint", Duration::from_secs(60), |env| {
+    ///     env.txn_checkpoint(0, 0, libdb::DB_NONE)
+    /// }).unwrap();
+    ///
+    /// env.shutdown(Duration::from_secs(30)).unwrap();
+    /// assert!(env.txn_stat(libdb::DB_NONE).unwrap().last_checkpoint_time > 0);
+    /// ```
+    pub fn txn_checkpoint(&self, kbytes: u32, minutes: u32, flags: Flags) -> Result<(), Error> {
+        unsafe {
+            check(ffi_call!(self.env_ptr, txn_checkpoint, kbytes, minutes, flags.bits()))
+        }
+    }
+
+    /// List the files described by `flags`: by default, the log files no longer needed
+    /// for recovery.
+    ///
+    /// Paths are relative to the environment home directory unless `ArchiveFlags::abs` is
+    /// given. A backup copies the `data` and `log` files; once it is done, the unneeded
+    /// log files can be deleted, or removed by libdb with `remove`.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let logs = env.log_archive(libdb::ArchiveFlags::new().log().abs()).unwrap();
+    /// assert!(logs.iter().all(|log| log.is_absolute() && log.starts_with(dir.path())));
+    ///
+    /// // No log file has been finished with yet.
+    /// assert!(env.log_archive(libdb::ArchiveFlags::new()).unwrap().is_empty());
+    /// # }
+    /// ```
+    pub fn log_archive(&self, flags: ArchiveFlags) -> Result<Vec<PathBuf>, Error> {
+        unsafe {
+            let mut list: *mut *mut c_char = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, log_archive, &mut list, flags.bits().bits()))?;
+            let mut paths = Vec::new();
+            if !list.is_null() {
+                // The list is a single allocation, terminated by a null pointer.
+                let mut entry = list;
+                while !(*entry).is_null() {
+                    paths.push(PathBuf::from(CStr::from_ptr(*entry).to_string_lossy().into_owned()));
+                    entry = entry.offset(1);
+                }
+                libc::free(list as *mut c_void);
+            }
+            Ok(paths)
+        }
+    }
+
+    /// Open a cursor for reading the records of the environment's log.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// env.txn_checkpoint(0, 0, libdb::DB_FORCE).unwrap();
+    ///
+    /// let mut cursor = env.log_cursor().unwrap();
+    /// let (first, _) = cursor.first().unwrap().unwrap();
+    /// let (last, record) = cursor.last().unwrap().unwrap();
+    /// assert!(first <= last);
+    ///
+    /// let (lsn, same) = cursor.set(last).unwrap().unwrap();
+    /// assert_eq!(last, lsn);
+    /// assert_eq!(record.as_slice(), same.as_slice());
+    /// ```
+    pub fn log_cursor<'a>(&'a self) -> Result<LogCursor<'a>, Error> {
+        unsafe {
+            let mut logc: *mut db_ffi::DB_LOGC = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, log_cursor, &mut logc, 0))?;
+            Ok(LogCursor { logc: logc, _env: PhantomData })
+        }
+    }
+
+    /// Return the statistics libdb prints for every subsystem, as text.
+    ///
+    /// This captures the output of `DB_ENV->stat_print`, which otherwise goes to
+    /// `EnvironmentBuilder::on_message`. Pass `DB_STAT_ALL` for more detail, and
+    /// `DB_STAT_CLEAR` to reset the counters once they are printed. Other messages the
+    /// environment prints while the report runs are captured too.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let report = env.stat_report(libdb::DB_NONE).unwrap();
+    /// assert!(!report.is_empty());
+    /// ```
+    pub fn stat_report(&self, flags: Flags) -> Result<String, Error> {
+        let _reporting = self.ctx.reporting.lock().unwrap();
+        *self.ctx.report.lock().unwrap() = Some(String::new());
+        let ret = unsafe {
+            if self.ctx.on_message.is_none() {
+                ffi_call!(self.env_ptr, set_msgcall, Some(message_callback));
+            }
+            let ret = check(ffi_call!(self.env_ptr, stat_print, flags.bits()));
+            if self.ctx.on_message.is_none() {
+                type MessageCallback = unsafe extern "C" fn(*const db_ffi::DB_ENV, *const c_char);
+                let none: Option<MessageCallback> = None;
+                ffi_call!(self.env_ptr, set_msgcall, none);
+            }
+            ret
+        };
+        let report = self.ctx.report.lock().unwrap().take().unwrap_or_default();
+        ret.map(|_| report)
+    }
+
+    /// Return statistics for the environment's lock subsystem.
+    ///
+    /// Pass `DB_STAT_CLEAR` to reset the counters once they are read.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+    ///     .open()
+    ///     .unwrap();
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// db.put(Some(&txn), b"key", b"value", libdb::DB_NONE).unwrap();
+    ///
+    /// let stat = env.lock_stat(libdb::DB_NONE).unwrap();
+    /// assert!(stat.locks > 0 && stat.locks <= stat.max_locks);
+    /// assert_eq!(0, stat.deadlocks);
+    /// # }
+    /// ```
+    pub fn lock_stat(&self, flags: Flags) -> Result<LockStat, Error> {
+        unsafe {
+            let mut stat: *mut db_ffi::DB_LOCK_STAT = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, lock_stat, &mut stat, flags.bits()))?;
+            let ret = LockStat::from_raw(&*stat);
+            libc::free(stat as *mut c_void);
+            Ok(ret)
+        }
+    }
+
+    /// Return statistics for the environment's log subsystem.
+    ///
+    /// Pass `DB_STAT_CLEAR` to reset the counters once they are read.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// env.txn_checkpoint(0, 0, libdb::DB_FORCE).unwrap();
+    ///
+    /// let stat = env.log_stat(libdb::DB_NONE).unwrap();
+    /// assert!(stat.records > 0);
+    /// assert!(stat.disk_lsn <= stat.current_lsn);
+    /// ```
+    pub fn log_stat(&self, flags: Flags) -> Result<LogStat, Error> {
+        unsafe {
+            let mut stat: *mut db_ffi::DB_LOG_STAT = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, log_stat, &mut stat, flags.bits()))?;
+            let ret = LogStat::from_raw(&*stat);
+            libc::free(stat as *mut c_void);
+            Ok(ret)
+        }
+    }
+
+    /// Clear the log sequence numbers in the pages of the database `file`, so it can be moved
+    /// into a different environment.
+    ///
+    /// Pages carry LSNs from their environment's log, which mean nothing to another
+    /// environment; opening a copied file there fails until they are reset. The file must
+    /// not be open. Pass `encrypted` if the database is encrypted. Fails with
+    /// `ErrorKind::ReadOnly` in a read-only environment.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home("/var/lib/app/db")
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// // "users.db" was copied in from another environment.
+    /// env.lsn_reset("users.db", false).unwrap();
+    /// ```
+    pub fn lsn_reset<P: AsRef<Path>>(&self, file: P, encrypted: bool) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::from_kind(ErrorKind::ReadOnly));
+        }
+        let file = CString::new(file.as_ref().to_str().unwrap()).unwrap();
+        let flags = if encrypted { DB_ENCRYPT } else { DB_NONE };
+        unsafe {
+            check(ffi_call!(self.env_ptr, lsn_reset, file.as_ptr(), flags.bits()))
+        }
+    }
+
+    /// Give the database `file` a new unique file ID, so a copy of a database can be opened
+    /// alongside the original in the same environment.
+    ///
+    /// The cache tells files apart by the ID stored in them, so until the copy's ID is
+    /// reset the two would share pages. The file must not be open. Pass `encrypted` if the
+    /// database is encrypted. Fails with `ErrorKind::ReadOnly` in a read-only environment.
+    pub fn fileid_reset<P: AsRef<Path>>(&self, file: P, encrypted: bool) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::from_kind(ErrorKind::ReadOnly));
+        }
+        let file = CString::new(file.as_ref().to_str().unwrap()).unwrap();
+        let flags = if encrypted { DB_ENCRYPT } else { DB_NONE };
+        unsafe {
+            check(ffi_call!(self.env_ptr, fileid_reset, file.as_ptr(), flags.bits()))
+        }
+    }
+
+    /// Write dirty pages until at least `percent` of the cache is clean, returning how many
+    /// pages were written.
+    ///
+    /// Running this periodically, e.g. from a background service, means threads reading
+    /// pages into the cache seldom have to write out a dirty page first. Fails with `EINVAL`
+    /// if `percent` is over 100.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// env.spawn_service("trickle", Duration::from_secs(1), |env| {
+    ///     env.memp_trickle(20).map(|_| ())
+    /// }).unwrap();
+    /// env.shutdown(Duration::from_secs(5)).unwrap();
+    /// ```
+    pub fn memp_trickle(&self, percent: u32) -> Result<u32, Error> {
+        if percent > 100 {
+            return Err(Error::new(libc::EINVAL));
+        }
+        let mut written: i32 = 0;
+        unsafe {
+            check(ffi_call!(self.env_ptr, memp_trickle, percent as i32, &mut written))?;
+        }
+        Ok(written as u32)
+    }
+
+    /// Write the cache's dirty pages to disk: all of them, or only those last modified at or
+    /// before `lsn`.
+    ///
+    /// Use this to get pages to disk ahead of a checkpoint, or before copying the database
+    /// files for a backup.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// env.memp_sync(None).unwrap();
+    ///
+    /// let lsn = env.log_stat(libdb::DB_NONE).unwrap().current_lsn;
+    /// env.memp_sync(Some(lsn)).unwrap();
+    /// ```
+    pub fn memp_sync(&self, lsn: Option<Lsn>) -> Result<(), Error> {
+        unsafe {
+            match lsn {
+                Some(lsn) => {
+                    let mut lsn = db_ffi::DB_LSN::from(lsn);
+                    check(ffi_call!(self.env_ptr, memp_sync, &mut lsn))
+                },
+                None => check(ffi_call!(self.env_ptr, memp_sync, ptr::null_mut())),
+            }
+        }
+    }
+
+    /// Return statistics for the environment's cache, including each file open in it.
+    ///
+    /// Pass `DB_STAT_CLEAR` to reset the counters once they are read.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .file("db")
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// db.put(None, b"key", b"value", libdb::DB_NONE).unwrap();
+    ///
+    /// let stat = env.memp_stat(libdb::DB_NONE).unwrap();
+    /// assert!(stat.hit_ratio() > 0.0);
+    /// assert!(stat.files.iter().any(|file| file.file == "db"));
+    /// # }
+    /// ```
+    pub fn memp_stat(&self, flags: Flags) -> Result<CacheStat, Error> {
+        unsafe {
+            let mut stat: *mut db_ffi::DB_MPOOL_STAT = ptr::null_mut();
+            let mut files: *mut *mut db_ffi::DB_MPOOL_FSTAT = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, memp_stat, &mut stat, &mut files, flags.bits()))?;
+            let ret = CacheStat::from_raw(&*stat, files);
+            libc::free(stat as *mut c_void);
+            // The file statistics are a single allocation.
+            libc::free(files as *mut c_void);
+            Ok(ret)
+        }
+    }
+
+    /// Return statistics for the environment's mutexes.
+    ///
+    /// Pass `DB_STAT_CLEAR` to reset the counters once they are read.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .mutex_increment(500)
+    ///     .open()
+    ///     .unwrap();
+    /// env.apply_config(&libdb::EnvTuning::new().mutex_tas_spins(200)).unwrap();
+    ///
+    /// let stat = env.mutex_stat(libdb::DB_NONE).unwrap();
+    /// assert_eq!(200, stat.tas_spins);
+    /// assert!(stat.in_use <= stat.mutexes);
+    /// # }
+    /// ```
+    pub fn mutex_stat(&self, flags: Flags) -> Result<MutexStat, Error> {
+        unsafe {
+            let mut stat: *mut db_ffi::DB_MUTEX_STAT = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, mutex_stat, &mut stat, flags.bits()))?;
+            let ret = MutexStat::from_raw(&*stat);
+            libc::free(stat as *mut c_void);
+            Ok(ret)
+        }
+    }
+
+    /// Return statistics for the environment's transaction subsystem.
+    ///
+    /// Pass `DB_STAT_CLEAR` to reset the counters once they are read.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// txn.set_name("import").unwrap();
+    ///
+    /// let stat = env.txn_stat(libdb::DB_NONE).unwrap();
+    /// assert_eq!(1, stat.active);
+    /// assert_eq!(Some(String::from("import")), stat.active_txns[0].name);
+    /// # }
+    /// ```
+    pub fn txn_stat(&self, flags: Flags) -> Result<TxnStat, Error> {
+        unsafe {
+            let mut stat: *mut db_ffi::DB_TXN_STAT = ptr::null_mut();
+            check(ffi_call!(self.env_ptr, txn_stat, &mut stat, flags.bits()))?;
+            let ret = TxnStat::from_raw(&*stat);
+            libc::free(stat as *mut c_void);
+            Ok(ret)
+        }
+    }
+
+    /// Wait up to `timeout` for the transaction identified by `token` to be durably applied
+    /// to this environment.
+    ///
+    /// Returns false if the wait timed out, and true once the commit is applied (or if the
+    /// transaction wrote nothing, so there is nothing to wait for). Fails with `DB_NOTFOUND`
+    /// if the transaction has been rolled back, or can no longer be found in the log.
+    #[cfg(feature = "v5_3")]
+    pub fn txn_applied(&self, token: &CommitToken, timeout: Duration) -> Result<bool, Error> {
+        let mut token = db_ffi::DB_TXN_TOKEN { buf: token.buf };
+        unsafe {
+            match ffi_call!(self.env_ptr, txn_applied, &mut token, timeout_micros(timeout), 0) {
+                0                  => Ok(true),
+                error::DB_KEYEMPTY => Ok(true),
+                db_ffi::DB_TIMEOUT => Ok(false),
+                e                  => Err(Error::new(e)),
+            }
+        }
+    }
+
+    /// Begin a new transaction in the environment.
+    pub fn txn(&self, parent: Option<&Transaction>, flags: TxnFlags) -> Result<Transaction, Error> {
+        Transaction::begin(self.env_ptr, &self.resources, unwrap_txn_ptr(parent), flags)
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        self.services.stop();
+
+        if ptr::null() == self.env_ptr {
+            return;
+        }
+        // Transactions do not keep the environment alive. Rather than free the state they
+        // still refer to, leave the environment for the last of them to close. The lock
+        // keeps that transaction from finishing between the check and the handoff.
+        let mut orphan = self.resources.orphan.lock().unwrap_or_else(|e| e.into_inner());
+        if self.resources.transactions.load(Ordering::SeqCst) == 0 {
+            unsafe {
+                ffi_call!(self.env_ptr, close, 0);
+            }
+        } else {
+            // libdb can still call back into the context through the open handle.
+            *orphan = Some(OrphanEnv {
+                env_ptr: self.env_ptr,
+                _ctx: mem::replace(&mut self.ctx, Box::new(EnvContext::new())),
+            });
+        }
+    }
+}
+
+/// State which libdb callbacks reach through `DB_ENV->app_private`.
+struct EnvContext {
+    // The handle generation reported by `Env::generation`, here so that panic events
+    // can advance it.
+    generation: AtomicUsize,
+    is_alive: Option<Box<dyn Fn(i32, u64, bool) -> bool + Send + Sync>>,
+    thread_id: Option<Box<dyn Fn() -> (i32, u64) + Send + Sync>>,
+    on_error: Option<Mutex<Box<dyn FnMut(&str, &str) + Send>>>,
+    on_message: Option<Mutex<Box<dyn FnMut(&str) + Send>>>,
+    on_event: Option<Mutex<Box<dyn FnMut(Event) + Send>>>,
+    // Messages captured by a running `Env::stat_report`, which `reporting` serializes.
+    report: Mutex<Option<String>>,
+    reporting: Mutex<()>,
+}
+
+impl EnvContext {
+    fn new() -> EnvContext {
+        EnvContext {
+            generation: AtomicUsize::new(0),
+            is_alive: None,
+            thread_id: None,
+            on_error: None,
+            on_message: None,
+            on_event: None,
+            report: Mutex::new(None),
+            reporting: Mutex::new(()),
+        }
+    }
+}
+
+/// DB_ENV->set_isalive callback which dispatches to the environment's closure.
+unsafe extern "C" fn is_alive_callback(env: *mut db_ffi::DB_ENV, pid: db_ffi::pid_t,
+                                       tid: db_ffi::db_threadid_t, flags: u32) -> i32 {
+    let ctx = &*((*env).app_private as *const EnvContext);
+    match ctx.is_alive {
+        // A panic must not unwind into libdb; like having no closure, it counts as alive.
+        Some(ref is_alive) => {
+            let process_only = flags & db_ffi::DB_MUTEX_PROCESS_ONLY != 0;
+            panic::catch_unwind(AssertUnwindSafe(|| is_alive(pid as i32, tid as u64, process_only)))
+                .unwrap_or(true) as i32
+        },
+        // Without a way to tell, assume the thread is alive.
+        None => 1,
+    }
+}
+
+/// DB_ENV->set_thread_id callback which dispatches to the environment's closure.
+unsafe extern "C" fn thread_id_callback(env: *mut db_ffi::DB_ENV, pid: *mut db_ffi::pid_t,
+                                        tid: *mut db_ffi::db_threadid_t) {
+    let ctx = &*((*env).app_private as *const EnvContext);
+    if let Some(ref thread_id) = ctx.thread_id {
+        // A panic must not unwind into libdb; fall back to the IDs libdb uses by default.
+        let (p, t) = panic::catch_unwind(AssertUnwindSafe(|| thread_id()))
+            .unwrap_or_else(|_| (libc::getpid() as i32, libc::pthread_self() as u64));
+        if !pid.is_null() {
+            *pid = p as db_ffi::pid_t;
+        }
+        if !tid.is_null() {
+            *tid = t as db_ffi::db_threadid_t;
+        }
+    }
+}
+
+/// DB_ENV->set_errcall callback which passes the message to the environment's closure.
+unsafe extern "C" fn error_callback(env: *const db_ffi::DB_ENV, prefix: *const c_char, message: *const c_char) {
+    let ctx = &*((*env).app_private as *const EnvContext);
+    if let Some(ref on_error) = ctx.on_error {
+        let prefix = cstr_to_string(prefix).unwrap_or_default();
+        let message = cstr_to_string(message).unwrap_or_default();
+        if let Ok(mut on_error) = on_error.lock() {
+            // A panic must not unwind into libdb. The guard outlives it, so the lock is not
+            // poisoned and later messages are still delivered.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| (&mut *on_error)(&prefix, &message)));
+        }
+    }
+}
+
+/// DB_ENV->set_msgcall callback which passes the message to the environment's closure.
+unsafe extern "C" fn message_callback(env: *const db_ffi::DB_ENV, message: *const c_char) {
+    let ctx = &*((*env).app_private as *const EnvContext);
+    let message = cstr_to_string(message).unwrap_or_default();
+    if let Ok(mut report) = ctx.report.lock() {
+        if let Some(ref mut report) = *report {
+            report.push_str(&message);
+            report.push('\n');
+            return;
+        }
+    }
+    if let Some(ref on_message) = ctx.on_message {
+        if let Ok(mut on_message) = on_message.lock() {
+            // A panic must not unwind into libdb. The guard outlives it, so the lock is not
+            // poisoned and later messages are still delivered.
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| (&mut *on_message)(&message)));
+        }
+    }
+}
+
+/// DB_ENV->set_event_notify callback which invalidates database handles when the
+/// environment panics, and passes the event to the environment's closure.
+unsafe extern "C" fn event_callback(env: *mut db_ffi::DB_ENV, event: u32, info: *mut c_void) {
+    let ctx = &*((*env).app_private as *const EnvContext);
+    if event == db_ffi::DB_EVENT_PANIC || event == db_ffi::DB_EVENT_REG_PANIC {
+        ctx.generation.fetch_add(1, Ordering::SeqCst);
+    }
+    if let Some(ref on_event) = ctx.on_event {
+        if let Ok(mut on_event) = on_event.lock() {
+            // A panic must not unwind into libdb. The guard outlives it, so the lock is not
+            // poisoned and later events are still delivered.
+            let event = Event::from_raw(event, info);
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| (&mut *on_event)(event)));
+        }
+    }
+}
+
+/// Environment settings which can be changed while the environment is open.
+///
+/// Settings which are not given are left as they are by `Env::apply_config`.
+#[derive(Clone, Debug, Default)]
+pub struct EnvTuning {
+    lock_timeout: Option<u32>,
+    txn_timeout: Option<u32>,
+    verbose: Vec<(Flags, bool)>,
+    cache_size: Option<u64>,
+    mutex_tas_spins: Option<u32>,
+    service_intervals: Vec<(String, Duration)>,
+}
+
+impl EnvTuning {
+    /// Create an empty set of settings.
+    pub fn new() -> EnvTuning {
+        Default::default()
+    }
+
+    /// Set how long a lock request may wait before it fails, at microsecond resolution.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout_micros(timeout));
+        self
+    }
+
+    /// Set how long a transaction may run before its lock requests fail, at microsecond
+    /// resolution.
+    pub fn txn_timeout(mut self, timeout: Duration) -> Self {
+        self.txn_timeout = Some(timeout_micros(timeout));
+        self
+    }
+
+    /// Turn the `DB_VERB_*` diagnostic messages in `which` on or off.
+    pub fn verbose(mut self, which: Flags, on: bool) -> Self {
+        self.verbose.push((which, on));
+        self
+    }
+
+    /// Resize the cache to `bytes`.
+    pub fn cache_size(mut self, bytes: u64) -> Self {
+        self.cache_size = Some(bytes);
+        self
+    }
+
+    /// Set how many times a thread spins on a contended mutex before blocking.
+    pub fn mutex_tas_spins(mut self, spins: u32) -> Self {
+        self.mutex_tas_spins = Some(spins);
+        self
+    }
+
+    /// Run the background services named `name`, such as a checkpointer or trickle writer
+    /// started by `Env::spawn_service`, every `interval`.
+    ///
+    /// The new interval is counted from the end of the service's last run. `apply_config`
+    /// fails with `ENOENT` if no service has that name.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// env.spawn_service("trickle", Duration::from_secs(60), |env| {
+    ///     env.memp_trickle(20).map(|_| ())
+    /// }).unwrap();
+    ///
+    /// let tuning = libdb::EnvTuning::new()
+    ///     .service_interval("trickle", Duration::from_secs(5));
+    /// assert!(env.apply_config(&tuning).is_ok());
+    /// ```
+    pub fn service_interval(mut self, name: &str, interval: Duration) -> Self {
+        self.service_intervals.push((name.to_string(), interval));
+        self
+    }
+}
+
+/// Which files `Env::log_archive` lists.
+///
+/// # Examples
+/// ```
+/// let flags = libdb::ArchiveFlags::new().data().abs();
+/// assert_eq!(libdb::DB_ARCH_DATA | libdb::DB_ARCH_ABS, flags.bits());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArchiveFlags {
+    list: Flags,
+    abs: Flags,
+}
+
+impl ArchiveFlags {
+    /// List the log files which are no longer needed for recovery.
+    pub fn new() -> ArchiveFlags {
+        ArchiveFlags { list: DB_NONE, abs: DB_NONE }
+    }
+
+    /// Return absolute paths, rather than paths relative to the home directory.
+    ///
+    /// Has no effect with `remove`, which lists nothing.
+    pub fn abs(mut self) -> Self {
+        if self.list != DB_ARCH_REMOVE {
+            self.abs = DB_ARCH_ABS;
+        }
+        self
+    }
+
+    /// List the database files which a backup needs.
+    pub fn data(mut self) -> Self {
+        self.list = DB_ARCH_DATA;
+        self
+    }
+
+    /// List every log file, whether or not it is still needed.
+    pub fn log(mut self) -> Self {
+        self.list = DB_ARCH_LOG;
+        self
+    }
+
+    /// Remove the log files which are no longer needed, listing nothing.
+    ///
+    /// libdb rejects `DB_ARCH_REMOVE` combined with any other flag, so this also clears
+    /// `abs`.
+    ///
+    /// # Examples
+    /// ```
+    /// let flags = libdb::ArchiveFlags::new().abs().remove();
+    /// assert_eq!(libdb::DB_ARCH_REMOVE, flags.bits());
+    /// ```
+    pub fn remove(mut self) -> Self {
+        self.list = DB_ARCH_REMOVE;
+        self.abs = DB_NONE;
+        self
+    }
+
+    /// Return the options as `DB_ENV->log_archive` flags.
+    pub fn bits(&self) -> Flags {
+        self.list | self.abs
+    }
+}
+
+impl Default for ArchiveFlags {
+    fn default() -> ArchiveFlags {
+        ArchiveFlags::new()
+    }
+}
+
+/// Which lock request the deadlock detector rejects to break a deadlock.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadlockPolicy {
+    /// Use the environment's default policy, `Random`.
+    Default = db_ffi::DB_LOCK_DEFAULT,
+    /// Reject only requests whose lock or transaction timeout has expired.
+    Expire = db_ffi::DB_LOCK_EXPIRE,
+    /// Reject the locker holding the most locks.
+    MaxLocks = db_ffi::DB_LOCK_MAXLOCKS,
+    /// Reject the locker holding the most write locks.
+    MaxWrite = db_ffi::DB_LOCK_MAXWRITE,
+    /// Reject the locker holding the fewest locks.
+    MinLocks = db_ffi::DB_LOCK_MINLOCKS,
+    /// Reject the locker holding the fewest write locks.
+    MinWrite = db_ffi::DB_LOCK_MINWRITE,
+    /// Reject the locker with the oldest lock.
+    Oldest = db_ffi::DB_LOCK_OLDEST,
+    /// Reject a locker at random.
+    Random = db_ffi::DB_LOCK_RANDOM,
+    /// Reject the locker with the youngest lock.
+    Youngest = db_ffi::DB_LOCK_YOUNGEST,
+}
+
+impl DeadlockPolicy {
+    /// Convert a `DB_LOCK_*` detector policy, of which 0 means none.
+    fn from_raw(detect: u32) -> Option<DeadlockPolicy> {
+        match detect {
+            db_ffi::DB_LOCK_DEFAULT  => Some(DeadlockPolicy::Default),
+            db_ffi::DB_LOCK_EXPIRE   => Some(DeadlockPolicy::Expire),
+            db_ffi::DB_LOCK_MAXLOCKS => Some(DeadlockPolicy::MaxLocks),
+            db_ffi::DB_LOCK_MAXWRITE => Some(DeadlockPolicy::MaxWrite),
+            db_ffi::DB_LOCK_MINLOCKS => Some(DeadlockPolicy::MinLocks),
+            db_ffi::DB_LOCK_MINWRITE => Some(DeadlockPolicy::MinWrite),
+            db_ffi::DB_LOCK_OLDEST   => Some(DeadlockPolicy::Oldest),
+            db_ffi::DB_LOCK_RANDOM   => Some(DeadlockPolicy::Random),
+            db_ffi::DB_LOCK_YOUNGEST => Some(DeadlockPolicy::Youngest),
+            _                        => None,
+        }
+    }
+}
+
+/// The kinds of object kept in an environment's shared regions, which
+/// `EnvironmentBuilder::memory_init` can allocate room for up front.
+#[cfg(feature = "v5_3")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionObject {
+    /// Locks.
+    Lock,
+    /// Objects which locks are held on.
+    LockObject,
+    /// Lockers, such as transactions.
+    Locker,
+    /// Log file IDs, one for each open database file.
+    LogId,
+    /// Transactions, including those prepared but not yet resolved.
+    Transaction,
+    /// Threads tracked for `Env::failchk`.
+    Thread,
+}
+
+#[cfg(feature = "v5_3")]
+impl From<RegionObject> for db_ffi::DB_MEM_CONFIG {
+    fn from(object: RegionObject) -> Self {
+        match object {
+            RegionObject::Lock => db_ffi::DB_MEM_CONFIG::DB_MEM_LOCK,
+            RegionObject::LockObject => db_ffi::DB_MEM_CONFIG::DB_MEM_LOCKOBJECT,
+            RegionObject::Locker => db_ffi::DB_MEM_CONFIG::DB_MEM_LOCKER,
+            RegionObject::LogId => db_ffi::DB_MEM_CONFIG::DB_MEM_LOGID,
+            RegionObject::Transaction => db_ffi::DB_MEM_CONFIG::DB_MEM_TRANSACTION,
+            RegionObject::Thread => db_ffi::DB_MEM_CONFIG::DB_MEM_THREAD,
+        }
+    }
+}
+
+/// An event reported by an environment to `EnvironmentBuilder::on_event`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The environment failed with the given error and must be recovered.
+    Panic(i32),
+    /// A process with the given ID is using an environment opened with `DB_REGISTER`, so
+    /// recovery is waiting for it.
+    RegisterAlive(i32),
+    /// A process opening the environment with `DB_REGISTER` found it needs recovery.
+    RegisterPanic,
+    /// This site has become a replication client.
+    RepClient,
+    /// This site won a replication election.
+    RepElected,
+    /// This site has become the replication master.
+    RepMaster,
+    /// The site with the given environment ID has become the replication master.
+    RepNewMaster(i32),
+    /// A transaction was not acknowledged by enough replication clients.
+    RepPermFailed,
+    /// This replication client has caught up with the master.
+    RepStartupDone,
+    /// A write to disk failed with the given error.
+    WriteFailed(i32),
+    /// An event this crate does not know, by its `DB_EVENT_*` number.
+    Other(u32),
+}
+
+impl Event {
+    /// Convert a `DB_EVENT_*` event and the information libdb passes with it.
+    unsafe fn from_raw(event: u32, info: *mut c_void) -> Event {
+        let int_info = || if info.is_null() { 0 } else { *(info as *const i32) };
+        match event {
+            db_ffi::DB_EVENT_PANIC           => Event::Panic(int_info()),
+            db_ffi::DB_EVENT_REG_ALIVE       => Event::RegisterAlive(int_info()),
+            db_ffi::DB_EVENT_REG_PANIC       => Event::RegisterPanic,
+            db_ffi::DB_EVENT_REP_CLIENT      => Event::RepClient,
+            db_ffi::DB_EVENT_REP_ELECTED     => Event::RepElected,
+            db_ffi::DB_EVENT_REP_MASTER      => Event::RepMaster,
+            db_ffi::DB_EVENT_REP_NEWMASTER   => Event::RepNewMaster(int_info()),
+            db_ffi::DB_EVENT_REP_PERM_FAILED => Event::RepPermFailed,
+            db_ffi::DB_EVENT_REP_STARTUPDONE => Event::RepStartupDone,
+            db_ffi::DB_EVENT_WRITE_FAILED    => Event::WriteFailed(int_info()),
+            _                                => Event::Other(event),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DbType {
+    BTree,
+    Hash,
+    Recno,
+    Queue,
+    Any,
+}
+
+impl From<DbType> for db_ffi::DBTYPE {
+    fn from(flavor: DbType) -> Self {
+        match flavor {
+            DbType::BTree => db_ffi::DBTYPE::DB_BTREE,
+            DbType::Hash => db_ffi::DBTYPE::DB_HASH,
+            DbType::Recno => db_ffi::DBTYPE::DB_RECNO,
+            DbType::Queue => db_ffi::DBTYPE::DB_QUEUE,
+            DbType::Any => db_ffi::DBTYPE::DB_UNKNOWN,
+        }
+    }
+}
+
+impl From<db_ffi::DBTYPE> for DbType {
+    fn from(flavor: db_ffi::DBTYPE) -> Self {
+        match flavor {
+            db_ffi::DBTYPE::DB_BTREE => DbType::BTree,
+            db_ffi::DBTYPE::DB_HASH => DbType::Hash,
+            db_ffi::DBTYPE::DB_RECNO => DbType::Recno,
+            db_ffi::DBTYPE::DB_QUEUE => DbType::Queue,
+            _ => DbType::Any,
+        }
+    }
+}
+
+/// The relative priority of pages in the memory pool cache.
+///
+/// Pages with a lower priority are evicted first, so a hot metadata database can be kept in
+/// cache while a database used for bulk scans is marked low priority.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CachePriority {
+    VeryLow,
+    Low,
+    Default,
+    High,
+    VeryHigh,
+}
+
+impl From<CachePriority> for db_ffi::DB_CACHE_PRIORITY {
+    fn from(priority: CachePriority) -> Self {
+        match priority {
+            CachePriority::VeryLow => db_ffi::DB_CACHE_PRIORITY::DB_PRIORITY_VERY_LOW,
+            CachePriority::Low => db_ffi::DB_CACHE_PRIORITY::DB_PRIORITY_LOW,
+            CachePriority::Default => db_ffi::DB_CACHE_PRIORITY::DB_PRIORITY_DEFAULT,
+            CachePriority::High => db_ffi::DB_CACHE_PRIORITY::DB_PRIORITY_HIGH,
+            CachePriority::VeryHigh => db_ffi::DB_CACHE_PRIORITY::DB_PRIORITY_VERY_HIGH,
+        }
+    }
+}
+
+/// `DatabaseBuilder` is used to configure and open a database.
+pub struct DatabaseBuilder<'a> {
+    // DatabaseBuilder must not outlive its environment.
+    //_env: std::marker::PhantomData<&'a Environment>,
+    env: Option<Environment>,
+    txn: Option<&'a Transaction>,
+    file: Option<CString>,
+    name: Option<CString>,
+    flags: Flags,
+    mode: i32,
+    db_type: DbType,
+    db_flags: Option<Flags>,
+    re_len: Option<u32>,
+    re_pad: Option<u8>,
+    re_delim: Option<u8>,
+    q_extentsize: Option<u32>,
+    h_ffactor: Option<u32>,
+    h_nelem: Option<u32>,
+    priority: Option<CachePriority>,
+    compression: Option<Compression>,
+    max_key_size: Option<usize>,
+    max_value_size: Option<usize>,
+    multiversion: bool,
+    encrypted: bool,
+    #[cfg(feature = "v5_3")]
+    partition: Option<Partition>,
+    #[cfg(feature = "v5_3")]
+    partition_dirs: Vec<CString>,
+}
+
+impl<'a> DatabaseBuilder<'a> {
+    /// Create a new DatabaseBuilder.
+    pub fn new() -> DatabaseBuilder<'a> {
+        DatabaseBuilder {
+            env: None,
+            txn: None,
+            file: None,
+            name: None,
+            flags: DB_NONE,
+            mode: 0,
+            db_type: DbType::BTree,
+            db_flags: None,
+            re_len: None,
+            re_pad: None,
+            re_delim: None,
+            q_extentsize: None,
+            h_ffactor: None,
+            h_nelem: None,
+            priority: None,
+            compression: None,
+            max_key_size: None,
+            max_value_size: None,
+            multiversion: false,
+            encrypted: false,
+            #[cfg(feature = "v5_3")]
+            partition: None,
+            #[cfg(feature = "v5_3")]
+            partition_dirs: Vec::new(),
+        }
+    }
+
+    /// Open the database within an environment.
+    pub fn environment(mut self, env: &Environment) -> Self {
+        self.env = Some(env.clone());
+        self
+    }
+
+    /// Open the database within a transaction.
+    pub fn transaction(mut self, txn: &'a Transaction) -> Self {
+        self.txn = Some(txn);
+        self
+    }
+
+    /// Specify the database file.
+    pub fn file<P: AsRef<Path>>(mut self, file: P) -> Self {
+        self.file = Some(CString::new(file.as_ref().to_str().unwrap()).unwrap());
+        self
+    }
+
+    /// Specify the database name.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(CString::new(name).unwrap());
+        self
+    }
+
+    /// Set the database flags.
+    pub fn flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the file mode.
+    pub fn mode(mut self, mode: i32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Keep multiple versions of the database's pages, so transactions begun with
+    /// `DB_TXN_SNAPSHOT` read a snapshot instead of taking read locks.
+    ///
+    /// Readers then never block writers, and writers only block each other. The
+    /// environment must be transactional. Copies of updated pages are kept in the cache
+    /// until the snapshot transactions which may read them finish, so size the cache for
+    /// the pages updated during the longest snapshot transaction on top of the working
+    /// set; `Env::set_mp_max_write` can stop writes from flooding the disk when old
+    /// versions are evicted.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG |
+    ///            libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+    ///     .multiversion()
+    ///     .open()
+    ///     .unwrap();
+    /// db.put(None, b"key", b"old", libdb::DB_NONE).unwrap();
+    ///
+    /// let reader = env.txn(None, libdb::TxnFlags::new().snapshot()).unwrap();
+    /// assert_eq!(b"old", db.get(Some(&reader), b"key", libdb::DB_NONE).unwrap().unwrap().as_slice());
+    ///
+    /// // The writer is not blocked by the reader, which keeps seeing its snapshot.
+    /// let writer = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// db.put(Some(&writer), b"key", b"new", libdb::DB_NONE).unwrap();
+    /// writer.commit(libdb::CommitType::Inherit).unwrap();
+    /// assert_eq!(b"old", db.get(Some(&reader), b"key", libdb::DB_NONE).unwrap().unwrap().as_slice());
+    /// reader.commit(libdb::CommitType::Inherit).unwrap();
+    /// ```
+    pub fn multiversion(mut self) -> Self {
+        self.multiversion = true;
+        self
+    }
+
+    /// Encrypt the database with the passphrase given to `EnvironmentBuilder::encrypt`.
+    ///
+    /// Databases in an encrypted environment are only encrypted if they ask to be, and
+    /// whether a database is encrypted cannot change once it is created.
+    pub fn encrypted(mut self) -> Self {
+        self.encrypted = true;
+        self
+    }
+
+    /// Set the database type.
+    pub fn db_type(mut self, db_type: DbType) -> Self {
+        self.db_type = db_type;
+        self
+    }
+
+    /// Set the database configuration flags, such as `DB_DUPSORT` or `DB_RECNUM`.
+    ///
+    /// These are passed to `DB->set_flags` and, unlike the open flags, describe how the
+    /// database is structured, so they must match the flags it was created with.
+    pub fn db_flags(mut self, flags: Flags) -> Self {
+        self.db_flags = Some(flags);
+        self
+    }
+
+    /// Set the length of fixed-length records.
+    ///
+    /// Queue databases require a record length. Setting it on a Recno database makes its
+    /// records fixed-length; shorter records are padded with the pad byte.
+    ///
+    /// # Examples
+    /// ```
+    /// let ret = libdb::DatabaseBuilder::new()
+    ///     .db_type(libdb::DbType::Recno)
+    ///     .record_len(8)
+    ///     .record_pad(b' ')
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open();
+    /// assert!(ret.is_ok());
+    /// ```
+    pub fn record_len(mut self, len: u32) -> Self {
+        self.re_len = Some(len);
+        self
+    }
+
+    /// Set the byte used to pad fixed-length records (Queue and Recno only).
+    pub fn record_pad(mut self, pad: u8) -> Self {
+        self.re_pad = Some(pad);
+        self
+    }
+
+    /// Set the byte which delimits variable-length records in a Recno backing source file.
+    pub fn record_delim(mut self, delim: u8) -> Self {
+        self.re_delim = Some(delim);
+        self
+    }
+
+    /// Set the number of pages in each Queue extent file.
+    ///
+    /// By default a Queue database is a single file. With an extent size set, the queue is
+    /// split into multiple extent files and extents that are no longer in use are removed.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let ret = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .file("queue")
+    ///     .db_type(libdb::DbType::Queue)
+    ///     .record_len(64)
+    ///     .queue_extent_size(1024)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open();
+    /// assert!(ret.is_ok());
+    /// # }
+    /// ```
+    pub fn queue_extent_size(mut self, pages: u32) -> Self {
+        self.q_extentsize = Some(pages);
+        self
+    }
+
+    /// Set the desired density of a Hash database's buckets.
+    ///
+    /// The fill factor is roughly `(pagesize - 32) / (average_key_size + average_data_size + 8)`.
+    pub fn hash_fill_factor(mut self, ffactor: u32) -> Self {
+        self.h_ffactor = Some(ffactor);
+        self
+    }
+
+    /// Set an estimate of the final number of elements in a Hash database.
+    ///
+    /// Sizing the table up front avoids repeatedly splitting buckets while it is loaded.
+    ///
+    /// # Examples
+    /// ```
+    /// let ret = libdb::DatabaseBuilder::new()
+    ///     .db_type(libdb::DbType::Hash)
+    ///     .hash_fill_factor(40)
+    ///     .hash_elements(100000)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open();
+    /// assert!(ret.is_ok());
+    /// ```
+    pub fn hash_elements(mut self, nelem: u32) -> Self {
+        self.h_nelem = Some(nelem);
+        self
+    }
+
+    /// Set the cache priority for the database's pages.
+    pub fn priority(mut self, priority: CachePriority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Reject keys longer than `size` bytes with `ErrorKind::RecordTooLarge`.
+    pub fn max_key_size(mut self, size: usize) -> Self {
+        self.max_key_size = Some(size);
+        self
+    }
+
+    /// Reject values longer than `size` bytes with `ErrorKind::RecordTooLarge`.
+    ///
+    /// Queue and fixed-length Recno databases always reject values longer than their
+    /// record length.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .max_key_size(8)
+    ///     .max_value_size(16)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let key   = String::from("key").into_bytes();
+    /// let value = vec![0; 17];
+    /// let ret = db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE);
+    /// assert_eq!(libdb::ErrorKind::RecordTooLarge, ret.unwrap_err().kind());
+    /// ```
+    pub fn max_value_size(mut self, size: usize) -> Self {
+        self.max_value_size = Some(size);
+        self
+    }
+
+    /// Compress btree pages with libdb's built-in prefix compression.
+    pub fn default_compression(mut self) -> Self {
+        self.compression = Some(Compression::Default);
+        self
+    }
+
+    /// Compress btree pages with custom functions.
+    ///
+    /// `compress` is called with the previous key/data pair on the page (empty for the
+    /// first) and the key/data pair to store, and returns its compressed encoding.
+    /// `decompress` is called with the previous pair and the compressed bytes remaining on
+    /// the page, and returns the number of bytes it consumed with the decoded key and data,
+    /// or `None` if the bytes are not valid. The functions must not panic, as they are called
+    /// from libdb.
+    ///
+    /// # Examples
+    /// ```
+    /// // Store each pair as the length of the key prefix it shares with the previous key,
+    /// // followed by the lengths of the rest of the key and the data.
+    /// fn compress(prev_key: &[u8], _prev_data: &[u8], key: &[u8], data: &[u8]) -> Vec<u8> {
+    ///     let shared = prev_key.iter().zip(key).take_while(|&(a, b)| a == b).count();
+    ///     let mut out = vec![shared as u8, (key.len() - shared) as u8, data.len() as u8];
+    ///     out.extend_from_slice(&key[shared..]);
+    ///     out.extend_from_slice(data);
+    ///     out
+    /// }
+    ///
+    /// fn decompress(prev_key: &[u8], _prev_data: &[u8], bytes: &[u8]) -> Option<(usize, Vec<u8>, Vec<u8>)> {
+    ///     if bytes.len() < 3 {
+    ///         return None;
+    ///     }
+    ///     let (shared, suffix, data) = (bytes[0] as usize, bytes[1] as usize, bytes[2] as usize);
+    ///     let mut key = prev_key.get(..shared)?.to_vec();
+    ///     key.extend_from_slice(bytes.get(3..3 + suffix)?);
+    ///     let data = bytes.get(3 + suffix..3 + suffix + data)?.to_vec();
+    ///     Some((3 + suffix + data.len(), key, data))
+    /// }
+    ///
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .compression(compress, decompress)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    /// let ret = db.get(None, key.as_slice(), libdb::DB_NONE).unwrap();
+    /// assert_eq!(b"value", ret.unwrap().as_slice());
+    /// ```
+    pub fn compression<C, D>(mut self, compress: C, decompress: D) -> Self
+        where C: Fn(&[u8], &[u8], &[u8], &[u8]) -> Vec<u8> + Send + Sync + 'static,
+              D: Fn(&[u8], &[u8], &[u8]) -> Option<(usize, Vec<u8>, Vec<u8>)> + Send + Sync + 'static
+    {
+        self.compression = Some(Compression::Custom(Box::new(compress), Box::new(decompress)));
+        self
+    }
+
+    /// Split the database into partitions at the given keys.
+    ///
+    /// `keys` holds the smallest key of every partition but the first, in sorted order, so
+    /// `n` keys create `n + 1` partitions.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate libdb;
+    /// # extern crate tempdir;
+    /// # fn main() {
+    /// let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .home(dir.path())
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let ret = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .file("partitioned")
+    ///     .partition_keys(vec![b"g".to_vec(), b"p".to_vec()])
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open();
+    /// assert!(ret.is_ok());
+    /// # }
+    /// ```
+    #[cfg(feature = "v5_3")]
+    pub fn partition_keys(mut self, keys: Vec<Vec<u8>>) -> Self {
+        self.partition = Some(Partition::Keys(keys, Vec::new()));
+        self
+    }
+
+    /// Split the database into `parts` partitions chosen by `callback`.
+    ///
+    /// The callback maps each key to a partition number; results are taken modulo `parts`.
+    /// It must not panic, as it is called from libdb.
+    #[cfg(feature = "v5_3")]
+    pub fn partition_callback<F>(mut self, parts: u32, callback: F) -> Self
+        where F: Fn(&[u8]) -> u32 + Send + Sync + 'static
+    {
+        self.partition = Some(Partition::Callback(parts, Box::new(callback)));
+        self
+    }
+
+    /// Spread the partitions across the given directories of the environment's data
+    /// directories.
+    #[cfg(feature = "v5_3")]
+    pub fn partition_dirs<P: AsRef<Path>>(mut self, dirs: &[P]) -> Self {
+        self.partition_dirs = dirs.iter()
+            .map(|dir| CString::new(dir.as_ref().to_str().unwrap()).unwrap())
+            .collect();
+        self
+    }
+
+    /// Apply the handle configuration which must be set before DB->open.
+    unsafe fn configure(&mut self, db: *mut db_ffi::DB, ctx: &mut DbContext) -> Result<(), Error> {
+        if self.encrypted {
+            self.db_flags = Some(self.db_flags.unwrap_or(DB_NONE) | DB_ENCRYPT);
+        }
+        if let Some(flags) = self.db_flags {
+            check(ffi_call!(db, set_flags, flags.bits()))?;
+        }
+        if let Some(len) = self.re_len {
+            check(ffi_call!(db, set_re_len, len))?;
+        }
+        if let Some(pad) = self.re_pad {
+            check(ffi_call!(db, set_re_pad, pad as i32))?;
+        }
+        if let Some(delim) = self.re_delim {
+            check(ffi_call!(db, set_re_delim, delim as i32))?;
+        }
+        if let Some(pages) = self.q_extentsize {
+            check(ffi_call!(db, set_q_extentsize, pages))?;
+        }
+        if let Some(ffactor) = self.h_ffactor {
+            check(ffi_call!(db, set_h_ffactor, ffactor))?;
+        }
+        if let Some(nelem) = self.h_nelem {
+            check(ffi_call!(db, set_h_nelem, nelem))?;
+        }
+        if let Some(priority) = self.priority {
+            check(ffi_call!(db, set_priority, db_ffi::DB_CACHE_PRIORITY::from(priority)))?;
+        }
+        if let Some(compression) = self.compression.take() {
+            type Compress = unsafe extern "C" fn(*mut db_ffi::DB, *const db_ffi::DBT, *const db_ffi::DBT,
+                                                 *const db_ffi::DBT, *const db_ffi::DBT, *mut db_ffi::DBT) -> i32;
+            type Decompress = unsafe extern "C" fn(*mut db_ffi::DB, *const db_ffi::DBT, *const db_ffi::DBT,
+                                                   *mut db_ffi::DBT, *mut db_ffi::DBT, *mut db_ffi::DBT) -> i32;
+            let (compress, decompress): (Option<Compress>, Option<Decompress>) = match compression {
+                Compression::Default      => (None, None),
+                Compression::Custom(_, _) => (Some(compress_callback), Some(decompress_callback)),
+            };
+            ctx.compression = Some(compression);
+            check(ffi_call!(db, set_bt_compress, compress, decompress))?;
+        }
+        #[cfg(feature = "v5_3")]
+        self.configure_partitions(db, ctx)?;
+        Ok(())
+    }
+
+    /// Apply the partitioning configuration, keeping what libdb points at alive in `ctx`.
+    #[cfg(feature = "v5_3")]
+    unsafe fn configure_partitions(&mut self, db: *mut db_ffi::DB, ctx: &mut DbContext) -> Result<(), Error> {
+        type Callback = unsafe extern "C" fn(*mut db_ffi::DB, *mut db_ffi::DBT) -> u32;
+
+        ctx.partition = self.partition.take();
+        match ctx.partition {
+            Some(Partition::Keys(ref mut keys, ref mut dbts)) => {
+                *dbts = keys.iter_mut().map(|key| {
+                    let mut dbt: db_ffi::DBT = Default::default();
+                    dbt.data = key.as_mut_ptr() as *mut c_void;
+                    dbt.size = key.len() as u32;
+                    dbt
+                }).collect();
+                let callback: Option<Callback> = None;
+                check(ffi_call!(db, set_partition, dbts.len() as u32 + 1, dbts.as_mut_ptr(), callback))?;
+            },
+            Some(Partition::Callback(parts, _)) => {
+                let callback: Option<Callback> = Some(partition_callback);
+                check(ffi_call!(db, set_partition, parts, ptr::null_mut(), callback))?;
+            },
+            None => (),
+        }
+
+        if !self.partition_dirs.is_empty() {
+            ctx.partition_dirs = self.partition_dirs.split_off(0);
+            let mut dirs: Vec<*const ::std::os::raw::c_char> =
+                ctx.partition_dirs.iter().map(|dir| dir.as_ptr()).collect();
+            dirs.push(ptr::null());
+            check(ffi_call!(db, set_partition_dirs, dirs.as_mut_ptr()))?;
+        }
+        Ok(())
+    }
+
+    /// Open the database represented by the file and database.
+    ///
+    /// # Panics
+    /// Panics if libdb fails to create the DB struct (e.g. malloc error).
+    pub fn open(mut self) -> Result<Database, Error> {
+        // Databases in a read-only environment must be opened read-only.
+        if self.env.as_ref().map_or(false, |env| env.is_read_only()) {
+            if self.flags.intersects(DB_CREATE | DB_TRUNCATE) {
+                return Err(Error::from_kind(ErrorKind::ReadOnly));
+            }
+            self.flags = self.flags | DB_RDONLY;
+        }
+        if self.multiversion {
+            self.flags = self.flags | DB_MULTIVERSION;
+        }
+
+        // Get the DB_ENV pointer
+        let env_ptr = match self.env.as_ref() {
+            Some(env) => env.env_ptr,
+            None      => ptr::null_mut()
+        };
+        
+        // Get the file name pointer.
+        let file_ptr = match self.file.as_ref() {
+            Some(cstr) => cstr.as_ptr(),
+            None => ptr::null()
+        };
+
+        // Get the database name pointer.
+        let database_ptr = match self.name.as_ref() {
+            Some(cstr) => cstr.as_ptr(),
+            None => ptr::null()
+        };
+
+        unsafe {
+            // Create the DB struct
+            let mut db: *mut db_ffi::DB = ptr::null_mut();
+            let ret = ffi_fn!(db_create, &mut db, env_ptr, 0);
+            if ret != 0 {
+                panic!("Could not instantiate DB. errno = {}", ret);
+            }
+
+            // Configure the handle
+            let mut ctx = Box::new(DbContext::new());
+            (*db).app_private = &mut *ctx as *mut DbContext as *mut c_void;
+            if let Err(e) = self.configure(db, &mut ctx) {
+                ffi_call!(db, close, 0);
+                return Err(e);
+            }
+
+            // Open the database
+            let dbtype = db_ffi::DBTYPE::from(self.db_type);
+            let ret = ffi_call!(db, open, unwrap_txn_ptr(self.txn), file_ptr, database_ptr, dbtype, self.flags.bits(), self.mode);
+            if ret != 0 {
+                ffi_call!(db, close, 0);
+                return Err(Error::new(ret));
+            }
+
+            // Fixed-length records cap the value size.
+            let max_value_size = match record_len(db) {
+                Ok(Some(len)) => Some(self.max_value_size.map_or(len, |max| max.min(len))),
+                Ok(None)      => self.max_value_size,
+                Err(e)        => {
+                    ffi_call!(db, close, 0);
+                    return Err(e);
+                },
+            };
+
+            let generation = self.env.as_ref().map_or(0, |env| env.generation());
+            if let Some(env) = self.env.as_ref() {
+                env.resources.databases.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(Arc::new(Db {
+                env: self.env,
+                db: db,
+                generation: generation,
+                max_key_size: self.max_key_size,
+                max_value_size: max_value_size,
+                read_only: self.flags.contains(DB_RDONLY),
+                ctx: ctx,
+            }))
+        }
+    }
+}
+
+/// `Database` is the handle for a single Berkeley DB database.
+///
+/// # Examples
+/// ```
+/// let ret = libdb::DatabaseBuilder::new()
+///     .flags(libdb::DB_CREATE)
+///     .open();
+/// assert!(ret.is_ok())
+/// ```
+pub struct Db {
+    env: Option<Environment>,
+    db: *mut db_ffi::DB,
+    generation: usize,
+    max_key_size: Option<usize>,
+    max_value_size: Option<usize>,
+    read_only: bool,
+    // Referenced by libdb through DB->app_private; must outlive the DB handle.
+    ctx: Box<DbContext>,
+}
+
+/// State which libdb callbacks reach through `DB->app_private`.
+struct DbContext {
+    compression: Option<Compression>,
+    #[cfg(feature = "v5_3")]
+    partition: Option<Partition>,
+    #[cfg(feature = "v5_3")]
+    partition_dirs: Vec<CString>,
+}
+
+impl DbContext {
+    fn new() -> DbContext {
+        DbContext {
+            compression: None,
+            #[cfg(feature = "v5_3")]
+            partition: None,
+            #[cfg(feature = "v5_3")]
+            partition_dirs: Vec::new(),
+        }
+    }
+}
+
+/// How btree pages are compressed.
+enum Compression {
+    /// libdb's built-in prefix compression.
+    Default,
+    /// The user's compress and decompress functions.
+    Custom(Box<dyn Fn(&[u8], &[u8], &[u8], &[u8]) -> Vec<u8> + Send + Sync>,
+           Box<dyn Fn(&[u8], &[u8], &[u8]) -> Option<(usize, Vec<u8>, Vec<u8>)> + Send + Sync>),
+}
+
+/// DB->set_bt_compress compression callback which dispatches to the handle's closure.
+unsafe extern "C" fn compress_callback(db: *mut db_ffi::DB,
+                                       prev_key: *const db_ffi::DBT, prev_data: *const db_ffi::DBT,
+                                       key: *const db_ffi::DBT, data: *const db_ffi::DBT,
+                                       dest: *mut db_ffi::DBT) -> i32 {
+    let ctx = &*((*db).app_private as *const DbContext);
+    let compressed = match ctx.compression {
+        Some(Compression::Custom(ref compress, _)) =>
+            compress(dbt_slice(prev_key), dbt_slice(prev_data), dbt_slice(key), dbt_slice(data)),
+        _ => return libc::EINVAL,
+    };
+    if !fill_dbt(dest, &compressed) {
+        return error::DB_BUFFER_SMALL;
+    }
+    0
+}
+
+/// DB->set_bt_compress decompression callback which dispatches to the handle's closure.
+unsafe extern "C" fn decompress_callback(db: *mut db_ffi::DB,
+                                         prev_key: *const db_ffi::DBT, prev_data: *const db_ffi::DBT,
+                                         compressed: *mut db_ffi::DBT,
+                                         dest_key: *mut db_ffi::DBT, dest_data: *mut db_ffi::DBT) -> i32 {
+    let ctx = &*((*db).app_private as *const DbContext);
+    let decompressed = match ctx.compression {
+        Some(Compression::Custom(_, ref decompress)) =>
+            decompress(dbt_slice(prev_key), dbt_slice(prev_data), dbt_slice(compressed)),
+        _ => return libc::EINVAL,
+    };
+    match decompressed {
+        Some((consumed, key, data)) => {
+            // Check both buffers first so libdb learns both sizes from a single retry.
+            let key_fits = fill_dbt(dest_key, &key);
+            let data_fits = fill_dbt(dest_data, &data);
+            if !key_fits || !data_fits {
+                return error::DB_BUFFER_SMALL;
+            }
+            (*compressed).size = consumed as u32;
+            0
+        },
+        None => libc::EINVAL,
+    }
+}
+
+/// How a partitioned database assigns keys to partitions.
+#[cfg(feature = "v5_3")]
+enum Partition {
+    /// Partition boundary keys, and the DBTs handed to libdb which point into them.
+    Keys(Vec<Vec<u8>>, Vec<db_ffi::DBT>),
+    /// The number of partitions and the function which picks one for a key.
+    Callback(u32, Box<dyn Fn(&[u8]) -> u32 + Send + Sync>),
+}
+
+/// DB->set_partition callback which dispatches to the closure in the handle's context.
+#[cfg(feature = "v5_3")]
+unsafe extern "C" fn partition_callback(db: *mut db_ffi::DB, key: *mut db_ffi::DBT) -> u32 {
+    let ctx = &*((*db).app_private as *const DbContext);
+    match ctx.partition {
+        Some(Partition::Callback(parts, ref callback)) => {
+            callback(dbt_slice(key)) % parts
+        },
+        _ => 0,
+    }
+}
+
+impl Db {
+    /// Fail if the environment has been recovered since this handle was opened.
+    fn check_generation(&self) -> Result<(), Error> {
+        match self.env.as_ref() {
+            Some(env) if env.generation() != self.generation =>
+                Err(Error::from_kind(ErrorKind::HandleInvalidated)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Was the database opened read-only?
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Return the database's type.
+    ///
+    /// This is how a database opened with `DbType::Any` learns what it is.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_type(libdb::DbType::Hash)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// assert_eq!(libdb::DbType::Hash, db.db_type().unwrap());
+    /// assert_eq!((None, None), db.dbname().unwrap());
+    /// assert!(db.open_flags().unwrap().contains(libdb::DB_CREATE));
+    /// assert!(!db.is_byteswapped().unwrap());
+    /// ```
+    pub fn db_type(&self) -> Result<DbType, Error> {
+        self.check_generation()?;
+        unsafe {
+            let mut db_type = db_ffi::DBTYPE::DB_UNKNOWN;
+            check(ffi_call!(self.db, get_type, &mut db_type))?;
+            Ok(DbType::from(db_type))
+        }
+    }
+
+    /// Return the database's file name and database name, if it has them.
+    ///
+    /// In-memory databases have no file name, and a database which is the only one in its
+    /// file has no database name.
+    pub fn dbname(&self) -> Result<(Option<String>, Option<String>), Error> {
+        self.check_generation()?;
+        unsafe {
+            let mut file: *const ::std::os::raw::c_char = ptr::null();
+            let mut name: *const ::std::os::raw::c_char = ptr::null();
+            check(ffi_call!(self.db, get_dbname, &mut file, &mut name))?;
+            Ok((cstr_to_string(file), cstr_to_string(name)))
+        }
+    }
+
+    /// Return the flags the database was opened with.
+    pub fn open_flags(&self) -> Result<Flags, Error> {
+        self.check_generation()?;
+        unsafe {
+            let mut flags = 0;
+            check(ffi_call!(self.db, get_open_flags, &mut flags))?;
+            Ok(Flags::from_bits_truncate(flags))
+        }
+    }
+
+    /// Was the database written on a machine of the other byte order?
+    ///
+    /// libdb converts its own metadata, but application data stored in the database, such
+    /// as integer keys, is not converted.
+    pub fn is_byteswapped(&self) -> Result<bool, Error> {
+        self.check_generation()?;
+        unsafe {
+            let mut swapped = 0;
+            check(ffi_call!(self.db, get_byteswapped, &mut swapped))?;
+            Ok(swapped != 0)
+        }
+    }
+
+    /// Fail if the database was opened read-only.
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::from_kind(ErrorKind::ReadOnly));
+        }
+        Ok(())
+    }
+
+    /// Fail if a key or value exceeds the database's size limits.
+    fn check_size(&self, key: &[u8], data_len: Option<usize>) -> Result<(), Error> {
+        let too_large = |len: usize, max: Option<usize>| max.map_or(false, |max| len > max);
+        if too_large(key.len(), self.max_key_size)
+            || data_len.map_or(false, |len| too_large(len, self.max_value_size)) {
+            return Err(Error::from_kind(ErrorKind::RecordTooLarge));
+        }
+        Ok(())
+    }
+
+    /// Fail unless the database is a Btree or Hash, the types whose `DB_MULTIPLE_KEY`
+    /// buffers hold keys rather than record numbers.
+    fn check_keyed(&self) -> Result<(), Error> {
+        match self.db_type()? {
+            DbType::BTree | DbType::Hash => Ok(()),
+            _ => Err(Error::new(libc::EINVAL)),
+        }
+    }
+
+    /// Build an `Error` for a failed call.
+    ///
+    /// A panic invalidates the environment's handles, and is reported as
+    /// `ErrorKind::HandleInvalidated` so callers know to reopen.
+    fn error(&self, errno: i32) -> Error {
+        if errno == error::DB_RUNRECOVERY {
+            if let Some(env) = self.env.as_ref() {
+                env.invalidate_handles();
+                return Error::from_kind(ErrorKind::HandleInvalidated);
+            }
+        }
+        Error::new(errno)
+    }
+
+    /// Get a key/data pair from the database.
+    ///
+    /// # Examples
+    ///
+    /// # Record Found
+    /// ```
+    /// # use std::str;
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// assert!(db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).is_ok());
+    ///
+    /// let ret = db.get(None, key.as_slice(), libdb::DB_NONE);
+    /// assert!(ret.is_ok());
+    /// assert_eq!("value", str::from_utf8(ret.ok().unwrap().unwrap().as_slice()).unwrap());
+    /// ```
+    ///
+    /// ## Record Not Found
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let key = String::from("key2").into_bytes();
+    /// let ret = db.get(None, key.as_slice(), libdb::DB_NONE);
+    /// println!("{:?}", ret);
+    /// assert!(ret.is_ok());
+    /// assert!(ret.unwrap().is_none());
+    /// ```
+    pub fn get(&self, txn: Option<&Transaction>, key: &[u8], flags: Flags) -> Result<Option<DBT>, Error> {
+        match self.lookup(txn, key, flags)? {
+            Lookup::Found(data) => Ok(Some(data)),
+            Lookup::NotFound    => Ok(None),
+            Lookup::Deleted     => Err(self.error(error::DB_KEYEMPTY)),
+        }
+    }
+
+    /// Get a key/data pair from the database, telling a missing key from a deleted record.
+    ///
+    /// In Queue and Recno databases a record number below the highest one in use may refer
+    /// to a record which was deleted, or never written; `get` reports these as a
+    /// `DB_KEYEMPTY` error, while `lookup` returns `Lookup::Deleted`.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_type(libdb::DbType::Queue)
+    ///     .record_len(8)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// db.append(None, b"first").unwrap();
+    /// db.append(None, b"second").unwrap();
+    /// db.consume(None, false).unwrap();
+    ///
+    /// let ret = db.lookup(None, &1u32.to_ne_bytes(), libdb::DB_NONE).unwrap();
+    /// assert!(matches!(ret, libdb::Lookup::Deleted));
+    /// let ret = db.lookup(None, &3u32.to_ne_bytes(), libdb::DB_NONE).unwrap();
+    /// assert!(matches!(ret, libdb::Lookup::NotFound));
+    /// ```
+    pub fn lookup<'a>(&'a self, txn: Option<&Transaction>, key: &[u8], flags: Flags) -> Result<Lookup<'a>, Error> {
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = key.as_ptr() as *mut ::std::os::raw::c_void;
+        key_dbt.size = key.len() as u32;
+
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.flags = db_ffi::DB_DBT_MALLOC;
+
+        self.check_generation()?;
+        self.check_size(key, None)?;
+        unsafe {
+            match ffi_call!(self.db, get, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, flags.bits()) {
+                0 => Ok(Lookup::Found(DBT::from(data_dbt))),
+                error::DB_NOTFOUND => Ok(Lookup::NotFound),
+                error::DB_KEYEMPTY => Ok(Lookup::Deleted),
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Store a key/data pair in the database.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// let ret = db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE);
+    /// assert!(ret.is_ok());
+    /// ```
+    pub fn put(&self, txn: Option<&Transaction>, key: &[u8], data: &[u8], flags: Flags) -> Result<(), Error> {
+        match self.put_op(txn, key, data, flags.bits())? {
+            0 => Ok(()),
+            e => Err(self.error(e))
+        }
+    }
+
+    /// Delete a key and its data from the database, returning whether it was there.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// db.put(None, b"key", b"value", libdb::DB_NONE).unwrap();
+    /// assert!(db.delete(None, b"key", libdb::DB_NONE).unwrap());
+    /// assert!(!db.delete(None, b"key", libdb::DB_NONE).unwrap());
+    /// ```
+    pub fn delete(&self, txn: Option<&Transaction>, key: &[u8], flags: Flags) -> Result<bool, Error> {
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = key.as_ptr() as *mut ::std::os::raw::c_void;
+        key_dbt.size = key.len() as u32;
+
+        self.check_generation()?;
+        self.check_writable()?;
+        self.check_size(key, None)?;
+        unsafe {
+            match ffi_call!(self.db, del, unwrap_txn_ptr(txn), &mut key_dbt, flags.bits()) {
+                0 => Ok(true),
+                error::DB_NOTFOUND => Ok(false),
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Store a key/data pair unless the key is already in the database.
+    ///
+    /// An existing key is reported as `PutResult::KeyExists` rather than an error, and its
+    /// data is left unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// let ret = db.put_no_overwrite(None, key.as_slice(), value.as_slice(), libdb::DB_NONE);
+    /// assert_eq!(libdb::PutResult::Inserted, ret.unwrap());
+    ///
+    /// let other = String::from("other").into_bytes();
+    /// let ret = db.put_no_overwrite(None, key.as_slice(), other.as_slice(), libdb::DB_NONE);
+    /// assert_eq!(libdb::PutResult::KeyExists, ret.unwrap());
+    /// ```
+    pub fn put_no_overwrite(&self, txn: Option<&Transaction>, key: &[u8], data: &[u8], flags: Flags) -> Result<PutResult, Error> {
+        match self.put_op(txn, key, data, db_ffi::DB_NOOVERWRITE | flags.bits())? {
+            0 => Ok(PutResult::Inserted),
+            error::DB_KEYEXIST => Ok(PutResult::KeyExists),
+            e => Err(self.error(e))
+        }
+    }
+
+    /// Helper which runs DB->put after the handle's checks, returning libdb's return code.
+    fn put_op(&self, txn: Option<&Transaction>, key: &[u8], data: &[u8], flags: u32) -> Result<i32, Error> {
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = key.as_ptr() as *mut ::std::os::raw::c_void;
+        key_dbt.size = key.len() as u32;
+
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.data = data.as_ptr() as *mut ::std::os::raw::c_void;
+        data_dbt.size = data.len() as u32;
+
+        self.check_generation()?;
+        self.check_writable()?;
+        self.check_size(key, Some(data.len()))?;
+        unsafe {
+            Ok(ffi_call!(self.db, put, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, flags))
+        }
+    }
+
+    /// Append a record to a Recno or Queue database, returning its record number.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_type(libdb::DbType::Recno)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let value = String::from("first").into_bytes();
+    /// assert_eq!(1, db.append(None, value.as_slice()).unwrap());
+    /// let value = String::from("second").into_bytes();
+    /// assert_eq!(2, db.append(None, value.as_slice()).unwrap());
+    ///
+    /// let key = 2u32.to_ne_bytes();
+    /// let ret = db.get(None, &key, libdb::DB_NONE).unwrap();
+    /// assert_eq!(b"second", ret.unwrap().as_slice());
+    /// ```
+    pub fn append(&self, txn: Option<&Transaction>, data: &[u8]) -> Result<u32, Error> {
+        // libdb writes the allocated record number into the key.
+        let mut recno: u32 = 0;
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = &mut recno as *mut u32 as *mut ::std::os::raw::c_void;
+        key_dbt.ulen = 4;
+        key_dbt.flags = db_ffi::DB_DBT_USERMEM;
+
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.data = data.as_ptr() as *mut ::std::os::raw::c_void;
+        data_dbt.size = data.len() as u32;
+
+        self.check_generation()?;
+        self.check_writable()?;
+        self.check_size(&[], Some(data.len()))?;
+        unsafe {
+            match ffi_call!(self.db, put, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, db_ffi::DB_APPEND) {
+                0 => Ok(recno),
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Remove the record at the head of a Queue database and return its record number and
+    /// data.
+    ///
+    /// Returns `None` if the queue is empty, unless `wait` is set, in which case the call
+    /// blocks until a record is appended. Waiting requires a locking environment.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_type(libdb::DbType::Queue)
+    ///     .record_len(8)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let value = String::from("job 1").into_bytes();
+    /// db.append(None, value.as_slice()).unwrap();
+    ///
+    /// let (recno, data) = db.consume(None, false).unwrap().unwrap();
+    /// assert_eq!(1, recno);
+    /// assert_eq!(b"job 1", &data[..5]);
+    /// assert!(db.consume(None, false).unwrap().is_none());
+    /// ```
+    pub fn consume<'a>(&'a self, txn: Option<&Transaction>, wait: bool) -> Result<Option<(u32, DBT<'a>)>, Error> {
+        let mut recno: u32 = 0;
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = &mut recno as *mut u32 as *mut ::std::os::raw::c_void;
+        key_dbt.ulen = 4;
+        key_dbt.flags = db_ffi::DB_DBT_USERMEM;
+
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.flags = db_ffi::DB_DBT_MALLOC;
+
+        let flags = if wait { db_ffi::DB_CONSUME_WAIT } else { db_ffi::DB_CONSUME };
+        self.check_generation()?;
+        self.check_writable()?;
+        unsafe {
+            match ffi_call!(self.db, get, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, flags) {
+                0 => Ok(Some((recno, DBT::from(data_dbt)))),
+                error::DB_NOTFOUND => Ok(None),
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Store a key/data pair, returning the data it replaced.
+    ///
+    /// The old record is read and overwritten through the same cursor, so the key is only
+    /// looked up once. In an environment with locking, the read takes a write lock
+    /// (`DB_RMW`), so no other transaction can change the record in between. With
+    /// duplicates, the first data item for the key is replaced.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// assert_eq!(None, db.replace(None, b"key", b"first").unwrap());
+    /// assert_eq!(Some(b"first".to_vec()), db.replace(None, b"key", b"second").unwrap());
+    ///
+    /// let ret = db.get(None, b"key", libdb::DB_NONE).unwrap();
+    /// assert_eq!(b"second", ret.unwrap().as_slice());
+    /// ```
+    pub fn replace(&self, txn: Option<&Transaction>, key: &[u8], data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.check_writable()?;
+        self.check_size(key, Some(data.len()))?;
+        // libdb rejects DB_RMW without a lock subsystem.
+        let locking = self.env.as_ref().map_or(false, |env| env.locking);
+        let rmw = if locking { DB_RMW } else { DB_NONE };
+        let mut cursor = self.write_cursor(txn, DB_NONE)?;
+        match cursor.get(Some(key), None, db_ffi::DB_SET | rmw.bits())? {
+            Some((_, old)) => {
+                cursor.put_at(key, data, CursorPut::Current)?;
+                Ok(Some(old.to_vec()))
+            },
+            None => {
+                cursor.put(key, data)?;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Does the database hold the exact key/data pair?
+    ///
+    /// In a database with sorted duplicates this finds the pair among the key's duplicates.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    ///
+    /// assert!(db.get_both(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap());
+    /// let other = String::from("other").into_bytes();
+    /// assert!(!db.get_both(None, key.as_slice(), other.as_slice(), libdb::DB_NONE).unwrap());
+    /// ```
+    pub fn get_both(&self, txn: Option<&Transaction>, key: &[u8], data: &[u8], flags: Flags) -> Result<bool, Error> {
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = key.as_ptr() as *mut ::std::os::raw::c_void;
+        key_dbt.size = key.len() as u32;
+
+        // libdb returns the matched data in its own allocation, so the caller's is not written.
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.data = data.as_ptr() as *mut ::std::os::raw::c_void;
+        data_dbt.size = data.len() as u32;
+        data_dbt.flags = db_ffi::DB_DBT_MALLOC;
+
+        self.check_generation()?;
+        self.check_size(key, None)?;
+        unsafe {
+            match ffi_call!(self.db, get, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, db_ffi::DB_GET_BOTH | flags.bits()) {
+                0 => {
+                    if data_dbt.data != data.as_ptr() as *mut ::std::os::raw::c_void {
+                        libc::free(data_dbt.data);
+                    }
+                    Ok(true)
+                },
+                error::DB_NOTFOUND => Ok(false),
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Get the smallest duplicate of `key` which is greater than or equal to `data`.
+    ///
+    /// The database must have been created with `DB_DUPSORT`.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_flags(libdb::DB_DUPSORT)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let key = String::from("fruit").into_bytes();
+    /// for name in &["apple", "cherry"] {
+    ///     let value = name.to_string().into_bytes();
+    ///     db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let probe = String::from("banana").into_bytes();
+    /// let ret = db.get_both_range(None, key.as_slice(), probe.as_slice()).unwrap();
+    /// assert_eq!(b"cherry", ret.unwrap().as_slice());
+    /// ```
+    pub fn get_both_range<'a>(&'a self, txn: Option<&Transaction>, key: &[u8], data: &[u8]) -> Result<Option<DBT<'a>>, Error> {
+        self.check_size(key, None)?;
+        let mut cursor = self.cursor(txn, DB_NONE)?;
+        Ok(cursor.get_both_range(key, data)?.map(|(_, data)| data))
+    }
+
+    /// Get up to `len` bytes of a record's data, starting at `offset`.
+    ///
+    /// Only the requested bytes are copied out of the database, so a small piece of a large
+    /// value can be read cheaply. Reading past the end of the data returns the bytes which
+    /// exist, possibly none.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("0123456789").into_bytes();
+    /// db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    ///
+    /// let ret = db.get_partial(None, key.as_slice(), 2, 3, libdb::DB_NONE).unwrap();
+    /// assert_eq!(b"234", ret.unwrap().as_slice());
+    /// ```
+    pub fn get_partial<'a>(&'a self, txn: Option<&Transaction>, key: &[u8], offset: u32, len: u32, flags: Flags) -> Result<Option<DBT<'a>>, Error> {
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = key.as_ptr() as *mut ::std::os::raw::c_void;
+        key_dbt.size = key.len() as u32;
+
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.flags = db_ffi::DB_DBT_MALLOC | db_ffi::DB_DBT_PARTIAL;
+        data_dbt.doff = offset;
+        data_dbt.dlen = len;
+
+        self.check_generation()?;
+        self.check_size(key, None)?;
+        unsafe {
+            match ffi_call!(self.db, get, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, flags.bits()) {
+                0 => Ok(Some(DBT::from(data_dbt))),
+                error::DB_NOTFOUND => Ok(None),
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Overwrite part of a record's data with `data`, starting at `offset`.
+    ///
+    /// The record grows if the write extends past its end; a gap between the old end and
+    /// `offset` is filled with zeros. A missing record is created.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("0123456789").into_bytes();
+    /// db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    ///
+    /// let patch = String::from("abc").into_bytes();
+    /// db.put_partial(None, key.as_slice(), patch.as_slice(), 2, libdb::DB_NONE).unwrap();
+    ///
+    /// let ret = db.get(None, key.as_slice(), libdb::DB_NONE).unwrap();
+    /// assert_eq!(b"01abc56789", ret.unwrap().as_slice());
+    /// ```
+    pub fn put_partial(&self, txn: Option<&Transaction>, key: &[u8], data: &[u8], offset: u32, flags: Flags) -> Result<(), Error> {
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = key.as_ptr() as *mut ::std::os::raw::c_void;
+        key_dbt.size = key.len() as u32;
+
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.data = data.as_ptr() as *mut ::std::os::raw::c_void;
+        data_dbt.size = data.len() as u32;
+        data_dbt.flags = db_ffi::DB_DBT_PARTIAL;
+        data_dbt.doff = offset;
+        data_dbt.dlen = data.len() as u32;
+
+        self.check_generation()?;
+        self.check_writable()?;
+        self.check_size(key, Some(offset as usize + data.len()))?;
+        unsafe {
+            match ffi_call!(self.db, put, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, flags.bits()) {
+                0 => Ok(()),
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Open a cursor on the database.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    ///
+    /// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// let (key, value) = cursor.next().unwrap().unwrap();
+    /// assert_eq!(b"key", key.as_slice());
+    /// assert_eq!(b"value", value.as_slice());
+    /// assert!(cursor.next().unwrap().is_none());
+    /// ```
+    pub fn cursor<'a>(&'a self, txn: Option<&Transaction>, flags: Flags) -> Result<Cursor<'a>, Error> {
+        self.check_generation()?;
+        unsafe {
+            let mut dbc: *mut db_ffi::DBC = ptr::null_mut();
+            match ffi_call!(self.db, cursor, unwrap_txn_ptr(txn), &mut dbc, flags.bits()) {
+                0 => {
+                    if let Some(env) = self.env.as_ref() {
+                        env.resources.cursors.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Ok(Cursor { db: self, dbc: dbc, read_flags: 0 })
+                },
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Open a cursor which reads with the given isolation.
+    ///
+    /// `ReadCommitted` and `ReadUncommitted` are set when the cursor is opened, so it never
+    /// holds read locks for the length of a scan; `ReadModifyWrite` is applied to each read.
+    /// Use `Cursor::set_isolation` to change it for later reads.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG |
+    ///            libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+    ///     .open()
+    ///     .unwrap();
+    /// db.put(None, b"key", b"value", libdb::DB_NONE).unwrap();
+    ///
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// let mut cursor = db.isolated_cursor(Some(&txn), libdb::Isolation::ReadCommitted).unwrap();
+    /// assert_eq!(b"value", cursor.next().unwrap().unwrap().1.as_slice());
+    /// ```
+    pub fn isolated_cursor<'a>(&'a self, txn: Option<&Transaction>, isolation: Isolation) -> Result<Cursor<'a>, Error> {
+        let flags = match isolation {
+            Isolation::ReadModifyWrite => DB_NONE,
+            other                      => other.flags(),
+        };
+        let mut cursor = self.cursor(txn, flags)?;
+        cursor.set_isolation(isolation);
+        Ok(cursor)
+    }
+
+    /// Get the data for each of `keys`, returning `None` for keys which are missing or,
+    /// in Queue and Recno databases, deleted.
+    ///
+    /// The lookups run through one cursor, opened once for the batch, rather than the
+    /// cursor `DB->get` opens for every call; `flags` are passed to each `DBC->get`. The
+    /// lookups share `txn`, so they see a consistent view of the database when one is
+    /// given. For keys already in sorted order, `get_sorted_batch` is faster.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// db.put(None, b"a", b"1", libdb::DB_NONE).unwrap();
+    /// db.put(None, b"c", b"3", libdb::DB_NONE).unwrap();
+    ///
+    /// let keys: Vec<&[u8]> = vec![b"c", b"b", b"a"];
+    /// let ret = db.get_many(None, keys, libdb::DB_NONE).unwrap();
+    /// assert_eq!(b"3", ret[0].as_ref().unwrap().as_slice());
+    /// assert!(ret[1].is_none());
+    /// assert_eq!(b"1", ret[2].as_ref().unwrap().as_slice());
+    /// ```
+    pub fn get_many<'a, 'k, I>(&'a self, txn: Option<&Transaction>, keys: I, flags: Flags) -> Result<Vec<Option<DBT<'a>>>, Error>
+        where I: IntoIterator<Item = &'k [u8]>
+    {
+        let keys = keys.into_iter();
+        let mut cursor = self.cursor(txn, DB_NONE)?;
+        let mut found = Vec::with_capacity(keys.size_hint().0);
+        for key in keys {
+            self.check_size(key, None)?;
+            found.push(match cursor.get(Some(key), None, db_ffi::DB_SET | flags.bits()) {
+                Ok(record) => record.map(|(_, data)| data),
+                Err(ref e) if e.errno() == error::DB_KEYEMPTY => None,
+                Err(e) => return Err(e),
+            });
+        }
+        Ok(found)
+    }
+
+    /// Get the data for each of `sorted_keys`, which must be in ascending bytewise order.
+    ///
+    /// The keys are looked up in a single pass of one cursor using `DB_SET_RANGE`, so keys
+    /// close together share the work of descending the btree. A key which lies before the
+    /// record found for an earlier key is known to be missing without a lookup. The result
+    /// holds an entry for each key, `None` if it is missing.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// for name in &["a", "c", "d"] {
+    ///     let key   = name.to_string().into_bytes();
+    ///     let value = format!("value {}", name).into_bytes();
+    ///     db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let keys: Vec<&[u8]> = vec![b"a", b"b", b"d", b"e"];
+    /// let ret = db.get_sorted_batch(None, &keys).unwrap();
+    /// assert_eq!(b"value a", ret[0].as_ref().unwrap().as_slice());
+    /// assert!(ret[1].is_none());
+    /// assert_eq!(b"value d", ret[2].as_ref().unwrap().as_slice());
+    /// assert!(ret[3].is_none());
+    /// ```
+    pub fn get_sorted_batch<'a>(&'a self, txn: Option<&Transaction>, sorted_keys: &[&[u8]]) -> Result<Vec<Option<DBT<'a>>>, Error> {
+        let mut results = Vec::with_capacity(sorted_keys.len());
+        let mut cursor = self.cursor(txn, DB_NONE)?;
+        // The record the cursor is on, at or after the last key looked up.
+        let mut ahead: Option<(DBT<'a>, DBT<'a>)> = None;
+        let mut exhausted = false;
+        for &key in sorted_keys {
+            self.check_size(key, None)?;
+            let behind = match ahead {
+                Some((ref found, _)) => key < found.as_slice(),
+                None                 => false,
+            };
+            if !behind && !exhausted {
+                ahead = cursor.set_range(key)?;
+                exhausted = ahead.is_none();
+            }
+            match ahead.take() {
+                Some((found, data)) if found.as_slice() == key => results.push(Some(data)),
+                other => {
+                    ahead = other;
+                    results.push(None);
+                },
+            }
+        }
+        Ok(results)
+    }
+
+    /// Open a cursor which may write to the database.
+    ///
+    /// In a Concurrent Data Store environment (`DB_INIT_CDB`) only cursors opened with
+    /// `DB_WRITECURSOR` may write, and only one may be open at a time. The cursor returned
+    /// here is opened that way in such an environment, and is the only kind which offers
+    /// `put` and `del`. Elsewhere it is an ordinary cursor, since libdb rejects
+    /// `DB_WRITECURSOR` outside a Concurrent Data Store.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_CDB | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let mut cursor = db.write_cursor(None, libdb::DB_NONE).unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// cursor.put(key.as_slice(), value.as_slice()).unwrap();
+    ///
+    /// // Read operations are available too.
+    /// assert_eq!(b"value", cursor.next().unwrap().unwrap().1.as_slice());
+    /// cursor.del().unwrap();
+    /// ```
+    pub fn write_cursor<'a>(&'a self, txn: Option<&Transaction>, flags: Flags) -> Result<WriteCursor<'a>, Error> {
+        self.check_writable()?;
+        let cds = self.env.as_ref().map_or(false, |env| env.concurrent_data_store);
+        let flags = if cds { flags | DB_WRITECURSOR } else { flags };
+        let cursor = self.cursor(txn, flags)?;
+        Ok(WriteCursor { cursor: cursor })
+    }
+
+    /// Iterate over the records in key order, copying each key and data out of the
+    /// database.
+    ///
+    /// The iterator holds a cursor opened with `txn` and `flags`. It stops after yielding an
+    /// error. It is double-ended, so `rev` reads the records from the last backwards
+    /// without scanning the database forwards first.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// for (key, value) in &[(b"a", b"1"), (b"b", b"2"), (b"c", b"3")] {
+    ///     db.put(None, *key, *value, libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let keys: Vec<Vec<u8>> = db.iter(None, libdb::DB_NONE).unwrap()
+    ///     .map(|record| record.unwrap().0)
+    ///     .filter(|key| key.as_slice() != b"b")
+    ///     .collect();
+    /// assert_eq!(vec![b"a".to_vec(), b"c".to_vec()], keys);
+    ///
+    /// // The latest two keys.
+    /// let latest: Vec<Vec<u8>> = db.iter(None, libdb::DB_NONE).unwrap()
+    ///     .rev()
+    ///     .take(2)
+    ///     .map(|record| record.unwrap().0)
+    ///     .collect();
+    /// assert_eq!(vec![b"c".to_vec(), b"b".to_vec()], latest);
+    /// ```
+    pub fn iter<'a>(&'a self, txn: Option<&Transaction>, flags: Flags) -> Result<Iter<'a>, Error> {
+        let cursor = self.cursor(txn, flags)?;
+        Ok(Iter {
+            front: cursor,
+            back: None,
+            front_last: None,
+            back_last: None,
+            done: false,
+        })
+    }
+
+    /// Iterate over every value stored under `key`, in duplicate order.
+    ///
+    /// Yields nothing if the key is not in the database. It stops after yielding an error.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_flags(libdb::DB_DUPSORT)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// for &(key, value) in &[(b"a", b"1"), (b"b", b"2"), (b"b", b"3")] {
+    ///     db.put(None, key, value, libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let values: Vec<Vec<u8>> = db.duplicates(None, b"b").unwrap()
+    ///     .map(|value| value.unwrap())
+    ///     .collect();
+    /// assert_eq!(vec![b"2".to_vec(), b"3".to_vec()], values);
+    /// ```
+    pub fn duplicates<'a>(&'a self, txn: Option<&Transaction>, key: &[u8]) -> Result<Duplicates<'a>, Error> {
+        let cursor = self.cursor(txn, DB_NONE)?;
+        Ok(Duplicates {
+            cursor: cursor,
+            key: key.to_vec(),
+            started: false,
+            done: false,
+        })
+    }
+
+    /// Create a `BulkReader` which reads the records in key order, a buffer of up to
+    /// `buffer_size` bytes at a time.
+    ///
+    /// Each buffer is filled with a single `DBC->get`, which is much cheaper than a call per
+    /// record for long sequential scans. The size is rounded up to a multiple of 1KB, and
+    /// the buffer grows if a single record does not fit in it. Fails with `EINVAL` unless
+    /// the database is a Btree or Hash, since libdb fills the buffer of a Recno or Queue
+    /// database with record numbers rather than keys.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// for i in 0..1000u32 {
+    ///     db.put(None, &i.to_be_bytes(), b"value", libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let mut reader = db.bulk_reader(None, 16 * 1024).unwrap();
+    /// let mut count = 0;
+    /// while let Some(batch) = reader.next_batch().unwrap() {
+    ///     for (key, value) in batch {
+    ///         assert_eq!(4, key.len());
+    ///         assert_eq!(b"value", value);
+    ///         count += 1;
+    ///     }
+    /// }
+    /// assert_eq!(1000, count);
+    /// ```
+    pub fn bulk_reader<'a>(&'a self, txn: Option<&Transaction>, buffer_size: usize) -> Result<BulkReader<'a>, Error> {
+        self.check_keyed()?;
+        let cursor = self.cursor(txn, DB_CURSOR_BULK)?;
+        Ok(BulkReader {
+            cursor: cursor,
+            buffer: vec![0u32; bulk_buffer_len(buffer_size)],
+            done: false,
+        })
+    }
+
+    /// Create a `BulkWriter` which stores key/data pairs in batches of up to `buffer_size`
+    /// bytes.
+    ///
+    /// Each batch is stored with a single `DB->put`, which is much cheaper than a put per
+    /// record. Only Btree and Hash databases can be loaded this way; storing a batch in any
+    /// other fails with `EINVAL`.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let mut writer = db.bulk_writer(None, 64 * 1024);
+    /// for i in 0..1000u32 {
+    ///     writer.put(&i.to_be_bytes(), b"value").unwrap();
+    /// }
+    /// assert_eq!(1000, writer.finish().unwrap());
+    ///
+    /// let key = 999u32.to_be_bytes();
+    /// assert!(db.get(None, &key, libdb::DB_NONE).unwrap().is_some());
+    /// ```
+    pub fn bulk_writer<'a>(&'a self, txn: Option<&'a Transaction>, buffer_size: usize) -> BulkWriter<'a> {
+        BulkWriter {
+            db: self,
+            txn: txn,
+            buffer_size: buffer_size,
+            data: Vec::new(),
+            offsets: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Delete the records whose keys fall in `range`, returning the number deleted.
+    ///
+    /// Records are deleted through a cursor, at most `batch_size` at a time. When `txn_env`
+    /// is given each batch runs in its own transaction, which bounds the number of locks held
+    /// and the amount of log written by any one transaction. Batches which have already
+    /// committed stay deleted if a later batch fails.
+    ///
+    /// Key comparisons are bytewise, matching the default btree ordering.
+    ///
+    /// # Panics
+    /// Panics if `batch_size` is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// for name in &["user:1", "user:2", "user:3", "group:1"] {
+    ///     let key   = name.to_string().into_bytes();
+    ///     let value = String::from("value").into_bytes();
+    ///     db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let ret = db.delete_range(None, libdb::KeyRange::Prefix(b"user:"), 2);
+    /// assert_eq!(3, ret.unwrap());
+    ///
+    /// let key = String::from("group:1").into_bytes();
+    /// assert!(db.get(None, key.as_slice(), libdb::DB_NONE).unwrap().is_some());
+    /// ```
+    pub fn delete_range(&self, txn_env: Option<&Environment>, range: KeyRange, batch_size: usize) -> Result<usize, Error> {
+        assert!(batch_size > 0, "batch_size must be greater than zero");
+        self.check_writable()?;
+
+        let mut deleted = 0;
+        let mut resume = range.start().map(|start| start.to_vec());
+        loop {
+            let txn = match txn_env {
+                Some(env) => Some(env.txn(None, TxnFlags::new())?),
+                None      => None,
+            };
+
+            let mut count = 0;
+            let mut done = false;
+            {
+                let mut cursor = self.write_cursor(txn.as_ref(), DB_NONE)?;
+                let mut entry = match resume.as_mut() {
+                    Some(start) => cursor.set_range(start.as_slice())?,
+                    None        => cursor.next()?,
+                };
+                loop {
+                    let key = match entry {
+                        Some((ref key, _)) if range.contains(key.as_slice()) => key.to_vec(),
+                        _ => { done = true; break; }
+                    };
+                    if count == batch_size {
+                        resume = Some(key);
+                        break;
+                    }
+                    cursor.del()?;
+                    count += 1;
+                    entry = cursor.next()?;
+                }
+            }
+
+            if let Some(txn) = txn {
+                txn.commit(CommitType::Inherit)?;
+            }
+            deleted += count;
+
+            if done {
+                return Ok(deleted);
+            }
+        }
+    }
+
+    /// Close the database, reporting any failure.
+    ///
+    /// Dropping the last handle closes the database too, but ignores errors. Fails with
+    /// `ErrorKind::HandlesOutstanding`, leaving the database open, if the handle is shared.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let shared = db.clone();
+    /// let err = db.close(libdb::CloseType::Sync).unwrap_err();
+    /// assert_eq!(libdb::ErrorKind::HandlesOutstanding, err.kind());
+    /// assert!(shared.close(libdb::CloseType::NoSync).is_ok());
+    /// ```
+    pub fn close(self: Arc<Self>, mode: CloseType) -> Result<(), Error> {
+        let mut db = match Arc::try_unwrap(self) {
+            Ok(db) => db,
+            Err(_) => return Err(Error::from_kind(ErrorKind::HandlesOutstanding)),
+        };
+        unsafe {
+            let ret = ffi_call!(db.db, close, mode as u32);
+            db.db = ptr::null_mut();
+            check(ret)
+        }
+    }
+
+    /// Change the cache priority for the database's pages.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// assert!(db.set_priority(libdb::CachePriority::VeryHigh).is_ok());
+    /// ```
+    pub fn set_priority(&self, priority: CachePriority) -> Result<(), Error> {
+        self.check_generation()?;
+        unsafe {
+            match ffi_call!(self.db, set_priority, db_ffi::DB_CACHE_PRIORITY::from(priority)) {
+                0 => Ok(()),
+                e => Err(self.error(e))
+            }
+        }
+    }
+
+    /// Write every record in the database to `out`, returning the number written.
+    ///
+    /// The stream begins with a header recording the database type, its `DB->set_flags`
+    /// flags, its page size, whether it is compressed with built-in or custom functions,
+    /// and an optional comparator identifier (e.g. `"by-date/2"`). `import` checks the
+    /// header against the database it loads into.
+    ///
+    /// libdb cannot report which comparison function a database uses, so the comparator
+    /// identifier is only a label: the caller names the key ordering on both sides, and
+    /// a dump taken under one name cannot silently be loaded under another.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    ///
+    /// let mut dump = Vec::new();
+    /// assert_eq!(1, db.export(None, Some("bytewise/1"), &mut dump).unwrap());
+    ///
+    /// let copy = libdb::DatabaseBuilder::new()
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// assert_eq!(1, copy.import(None, Some("bytewise/1"), &mut dump.as_slice()).unwrap());
+    ///
+    /// let ret = copy.import(None, Some("reversed/1"), &mut dump.as_slice());
+    /// assert_eq!(libdb::ErrorKind::MetadataMismatch, ret.unwrap_err().kind());
+    /// ```
+    pub fn export<W: Write>(&self, txn: Option<&Transaction>, comparator: Option<&str>, out: &mut W) -> Result<usize, Error> {
+        let header = self.export_header(comparator)?;
+        out.write_all(EXPORT_MAGIC)?;
+        write_u32(out, db_ffi::DBTYPE::from(header.db_type) as u32)?;
+        write_u32(out, header.flags)?;
+        write_u32(out, header.pagesize)?;
+        write_u32(out, header.compression)?;
+        write_bytes(out, header.comparator.as_ref().map_or(&[][..], |c| c.as_bytes()))?;
+
+        let mut count = 0;
+        let mut cursor = self.cursor(txn, DB_NONE)?;
+        while let Some((key, data)) = cursor.next()? {
+            write_bytes(out, &key)?;
+            write_bytes(out, &data)?;
+            count += 1;
+        }
+        write_u32(out, EXPORT_END)?;
+        Ok(count)
+    }
+
+    /// Load records written by `export`, returning the number loaded.
+    ///
+    /// Fails with `ErrorKind::MetadataMismatch` before loading anything if the stream's header
+    /// does not match this database's configuration and `comparator`.
+    pub fn import<R: Read>(&self, txn: Option<&Transaction>, comparator: Option<&str>, input: &mut R) -> Result<usize, Error> {
+        self.check_writable()?;
+
+        let mut magic = [0; 18];
+        input.read_exact(&mut magic)?;
+        if &magic[..] != EXPORT_MAGIC {
+            return Err(Error::from_kind(ErrorKind::MetadataMismatch));
+        }
+
+        let db_type = read_u32(input)?;
+        let flags = read_u32(input)?;
+        let pagesize = read_u32(input)?;
+        let compression = read_u32(input)?;
+        let stream_comparator = read_bytes(input)?;
+
+        let header = self.export_header(comparator)?;
+        if db_type != db_ffi::DBTYPE::from(header.db_type) as u32
+            || flags != header.flags
+            || pagesize != header.pagesize
+            || compression != header.compression
+            || stream_comparator.as_slice() != comparator.map_or(&[][..], |c| c.as_bytes()) {
+            return Err(Error::from_kind(ErrorKind::MetadataMismatch));
+        }
+
+        let mut count = 0;
+        loop {
+            let key = match read_u32(input)? {
+                EXPORT_END => return Ok(count),
+                len        => read_exact_vec(input, len)?,
+            };
+            let data = read_bytes(input)?;
+            self.put(txn, key.as_slice(), data.as_slice(), DB_NONE)?;
+            count += 1;
+        }
+    }
+
+    /// Describe the configuration an export stream must match.
+    fn export_header(&self, comparator: Option<&str>) -> Result<ExportHeader, Error> {
+        let db_type = self.db_type()?;
+        unsafe {
+            let mut flags = 0;
+            check(ffi_call!(self.db, get_flags, &mut flags))?;
+            let mut pagesize = 0;
+            check(ffi_call!(self.db, get_pagesize, &mut pagesize))?;
+            let compression = match self.ctx.compression {
+                None                          => EXPORT_UNCOMPRESSED,
+                Some(Compression::Default)    => EXPORT_DEFAULT_COMPRESSION,
+                Some(Compression::Custom(..)) => EXPORT_CUSTOM_COMPRESSION,
+            };
+            Ok(ExportHeader {
+                db_type: db_type,
+                flags: flags,
+                pagesize: pagesize,
+                compression: compression,
+                comparator: comparator.map(String::from),
+            })
+        }
+    }
+}
+
+/// Magic bytes which begin a stream written by `Db::export`.
+const EXPORT_MAGIC: &'static [u8; 18] = b"libdb-rs export 2\n";
+
+/// Length marker which ends the records in an export stream.
+const EXPORT_END: u32 = 0xFFFFFFFF;
+
+/// The compression recorded in an export stream's header.
+const EXPORT_UNCOMPRESSED: u32 = 0;
+const EXPORT_DEFAULT_COMPRESSION: u32 = 1;
+const EXPORT_CUSTOM_COMPRESSION: u32 = 2;
+
+/// The configuration recorded at the head of an export stream.
+struct ExportHeader {
+    db_type: DbType,
+    flags: u32,
+    pagesize: u32,
+    compression: u32,
+    comparator: Option<String>,
+}
+
+impl Drop for Db {
+    fn drop(&mut self) {
+        if ptr::null() != self.db {
+            unsafe {
+                ffi_call!(self.db, close, 0);
+            }
+        }
+        if let Some(env) = self.env.as_ref() {
+            env.resources.databases.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The set of keys selected by `Db::delete_range`.
+pub enum KeyRange<'k> {
+    /// Keys greater than or equal to the first bound and less than the second.
+    /// A `None` bound leaves that end of the range open.
+    Between(Option<&'k [u8]>, Option<&'k [u8]>),
+    /// Keys which begin with the given bytes.
+    Prefix(&'k [u8]),
+}
+
+impl<'k> KeyRange<'k> {
+    /// The smallest key which may be in the range, if bounded.
+    fn start(&self) -> Option<&'k [u8]> {
+        match *self {
+            KeyRange::Between(start, _) => start,
+            KeyRange::Prefix(prefix)    => Some(prefix),
+        }
+    }
+
+    /// Does the range contain a key at or past its start?
+    fn contains(&self, key: &[u8]) -> bool {
+        match *self {
+            KeyRange::Between(_, Some(end)) => key < end,
+            KeyRange::Between(_, None)      => true,
+            KeyRange::Prefix(prefix)        => key.starts_with(prefix),
+        }
+    }
+}
+
+/// Whether `Db::close` flushes the database's cached pages.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum CloseType {
+    /// Write cached pages to the database file before closing.
+    Sync = 0,
+    /// Do not write cached pages. Only safe when the database is transactional or can be
+    /// recreated, since unwritten changes are otherwise lost.
+    NoSync = db_ffi::DB_NOSYNC,
+}
+
+/// The isolation a cursor reads with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Isolation {
+    /// Hold read locks until the transaction ends (degree 3 isolation). The default.
+    Serializable,
+    /// Release read locks as the cursor moves on (degree 2 isolation).
+    ReadCommitted,
+    /// Read modified data which has not been committed yet (degree 1 isolation). The
+    /// database must be opened with `DB_READ_UNCOMMITTED`.
+    ReadUncommitted,
+    /// Take write locks when reading, to avoid deadlocks when the records read are then
+    /// updated.
+    ReadModifyWrite,
+}
+
+impl Isolation {
+    fn flags(self) -> Flags {
+        match self {
+            Isolation::Serializable    => DB_NONE,
+            Isolation::ReadCommitted   => DB_READ_COMMITTED,
+            Isolation::ReadUncommitted => DB_READ_UNCOMMITTED,
+            Isolation::ReadModifyWrite => DB_RMW,
+        }
+    }
+}
+
+/// Where `WriteCursor::put_at` stores a record.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorPut {
+    /// Store the pair under its key, before any duplicates of it in an unsorted
+    /// duplicates database.
+    KeyFirst = db_ffi::DB_KEYFIRST,
+    /// Store the pair under its key, after any duplicates of it in an unsorted duplicates
+    /// database.
+    KeyLast = db_ffi::DB_KEYLAST,
+    /// Store the data as a duplicate immediately before the record under the cursor.
+    Before = db_ffi::DB_BEFORE,
+    /// Store the data as a duplicate immediately after the record under the cursor.
+    After = db_ffi::DB_AFTER,
+    /// Overwrite the data of the record under the cursor.
+    Current = db_ffi::DB_CURRENT,
+}
+
+/// The outcome of `Db::lookup`.
+#[derive(Debug)]
+pub enum Lookup<'a> {
+    /// The key's data.
+    Found(DBT<'a>),
+    /// The key is not in the database.
+    NotFound,
+    /// The record number refers to a deleted or never written Queue or Recno record.
+    Deleted,
+}
+
+/// The outcome of `Db::put_no_overwrite`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PutResult {
+    /// The key/data pair was stored.
+    Inserted,
+    /// The key was already in the database, so nothing was stored.
+    KeyExists,
+}
+
+/// A `Cursor` iterates over the records in a database.
+///
+/// The cursor is closed when it is dropped. It borrows the database handle it was opened
+/// from, so the handle cannot be closed or dropped while the cursor is open:
+///
+/// ```compile_fail
+/// # let db = libdb::DatabaseBuilder::new()
+/// #    .flags(libdb::DB_CREATE)
+/// #    .open()
+/// #    .unwrap();
+/// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+/// db.close(libdb::CloseType::Sync).unwrap();
+/// cursor.next().unwrap();
+/// ```
+pub struct Cursor<'a> {
+    db: &'a Db,
+    dbc: *mut db_ffi::DBC,
+    // Isolation flags added to every read.
+    read_flags: u32,
+}
+
+impl<'a> Cursor<'a> {
+    /// Move the cursor to the first record and return it.
+    ///
+    /// Returns `None` if the database is empty.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// for key in &[b"a", b"b", b"c"] {
+    ///     db.put(None, *key, b"value", libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// assert_eq!(b"a", cursor.first().unwrap().unwrap().0.as_slice());
+    /// assert_eq!(b"c", cursor.last().unwrap().unwrap().0.as_slice());
+    /// assert_eq!(b"b", cursor.prev().unwrap().unwrap().0.as_slice());
+    /// assert_eq!(b"b", cursor.current().unwrap().unwrap().0.as_slice());
+    /// ```
+    pub fn first(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_FIRST)
+    }
+
+    /// Move the cursor to the last record and return it.
+    ///
+    /// Returns `None` if the database is empty.
+    pub fn last(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_LAST)
+    }
+
+    /// Move the cursor to the next record and return it.
+    ///
+    /// A new cursor moves to the first record. Returns `None` after the last record.
+    pub fn next(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_NEXT)
+    }
+
+    /// Move the cursor to the previous record and return it.
+    ///
+    /// A new cursor moves to the last record. Returns `None` before the first record.
+    pub fn prev(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_PREV)
+    }
+
+    /// Return the record under the cursor without moving it.
+    ///
+    /// Fails with `DB_KEYEMPTY` if the record has been deleted.
+    pub fn current(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_CURRENT)
+    }
+
+    /// Move the cursor to the next duplicate of the current key and return it.
+    ///
+    /// Returns `None` after the key's last duplicate.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_flags(libdb::DB_DUPSORT)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// for &(key, value) in &[(b"a", b"1"), (b"a", b"2"), (b"b", b"1")] {
+    ///     db.put(None, key, value, libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// cursor.first().unwrap();
+    /// assert_eq!(b"2", cursor.next_dup().unwrap().unwrap().1.as_slice());
+    /// assert!(cursor.next_dup().unwrap().is_none());
+    /// assert_eq!(b"b", cursor.next_nodup().unwrap().unwrap().0.as_slice());
+    /// assert_eq!(b"a", cursor.prev_nodup().unwrap().unwrap().0.as_slice());
+    /// ```
+    pub fn next_dup(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_NEXT_DUP)
+    }
+
+    /// Move the cursor to the previous duplicate of the current key and return it.
+    ///
+    /// Returns `None` before the key's first duplicate.
+    pub fn prev_dup(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_PREV_DUP)
+    }
+
+    /// Move the cursor to the first record of the next key and return it.
+    ///
+    /// A new cursor moves to the first record. Returns `None` after the last key.
+    pub fn next_nodup(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_NEXT_NODUP)
+    }
+
+    /// Move the cursor to the last record of the previous key and return it.
+    ///
+    /// A new cursor moves to the last record. Returns `None` before the first key.
+    pub fn prev_nodup(&mut self) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(None, None, db_ffi::DB_PREV_NODUP)
+    }
+
+    /// Move the cursor to `key` and return its record.
+    ///
+    /// Returns `None`, leaving the cursor where it was, if the key is not in the database.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// db.put(None, b"apple", b"1", libdb::DB_NONE).unwrap();
+    /// db.put(None, b"cherry", b"2", libdb::DB_NONE).unwrap();
+    ///
+    /// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// assert!(cursor.set(b"banana").unwrap().is_none());
+    /// let (key, _) = cursor.set_range(b"banana").unwrap().unwrap();
+    /// assert_eq!(b"cherry", key.as_slice());
+    /// ```
+    pub fn set(&mut self, key: &[u8]) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(Some(key), None, db_ffi::DB_SET)
+    }
+
+    /// Move the cursor to the smallest key greater than or equal to `key` and return its
+    /// record.
+    ///
+    /// Returns `None` if every key is smaller.
+    pub fn set_range(&mut self, key: &[u8]) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(Some(key), None, db_ffi::DB_SET_RANGE)
+    }
+
+    /// Set the isolation of the cursor's later reads.
+    ///
+    /// Isolation chosen when the cursor was opened still applies, so setting `Serializable`
+    /// does not undo `ReadCommitted` on a cursor opened with it.
+    pub fn set_isolation(&mut self, isolation: Isolation) {
+        self.read_flags = isolation.flags().bits();
+    }
+
+    /// Open another cursor on the same database and transaction, positioned on the same
+    /// record.
+    ///
+    /// The new cursor moves independently, so it can look ahead while this one keeps its
+    /// place.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// for key in &[b"a", b"b", b"c"] {
+    ///     db.put(None, *key, b"value", libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// cursor.first().unwrap();
+    ///
+    /// let mut ahead = cursor.dup().unwrap();
+    /// assert_eq!(b"c", ahead.last().unwrap().unwrap().0.as_slice());
+    /// assert_eq!(b"a", cursor.current().unwrap().unwrap().0.as_slice());
+    /// ```
+    pub fn dup(&self) -> Result<Cursor<'a>, Error> {
+        self.db.check_generation()?;
+        unsafe {
+            let mut dbc: *mut db_ffi::DBC = ptr::null_mut();
+            match ffi_call!(self.dbc, dup, &mut dbc, db_ffi::DB_POSITION) {
+                0 => {
+                    if let Some(env) = self.db.env.as_ref() {
+                        env.resources.cursors.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Ok(Cursor { db: self.db, dbc: dbc, read_flags: self.read_flags })
+                },
+                e => Err(self.db.error(e))
+            }
+        }
+    }
+
+    /// Return the number of duplicates of the key under the cursor, including the record
+    /// itself.
+    ///
+    /// Databases without duplicates always return 1.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_flags(libdb::DB_DUPSORT)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// for value in &[b"1", b"2", b"3"] {
+    ///     db.put(None, b"key", *value, libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// cursor.set(b"key").unwrap();
+    /// assert_eq!(3, cursor.count().unwrap());
+    /// ```
+    pub fn count(&self) -> Result<u32, Error> {
+        self.db.check_generation()?;
+        let mut count: u32 = 0;
+        unsafe {
+            match ffi_call!(self.dbc, count, &mut count, 0) {
+                0 => Ok(count),
+                e => Err(self.db.error(e))
+            }
+        }
+    }
+
+    /// Move the cursor to record number `recno` and return the record.
+    ///
+    /// Only works on Btree databases opened with `DB_RECNUM`; in Recno and Queue databases
+    /// the record number is the key, so position the cursor with `set` instead. Record
+    /// numbers start at 1. Returns `None` if there is no such record.
+    ///
+    /// # Examples
+    /// ```
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .db_type(libdb::DbType::BTree)
+    ///     .db_flags(libdb::DB_RECNUM)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    /// for key in &[b"a", b"b", b"c"] {
+    ///     db.put(None, *key, b"value", libdb::DB_NONE).unwrap();
+    /// }
+    ///
+    /// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// assert_eq!(b"b", cursor.set_recno(2).unwrap().unwrap().0.as_slice());
+    /// cursor.next().unwrap();
+    /// assert_eq!(3, cursor.get_recno().unwrap());
+    /// ```
+    pub fn set_recno(&mut self, recno: u32) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        let key = recno.to_ne_bytes();
+        self.get(Some(&key), None, db_ffi::DB_SET_RECNO)
+    }
+
+    /// Return the record number of the record under the cursor.
+    ///
+    /// Only works on Btree databases opened with `DB_RECNUM`; in Recno and Queue databases
+    /// the record number is the key.
+    pub fn get_recno(&mut self) -> Result<u32, Error> {
+        match self.get(None, None, db_ffi::DB_GET_RECNO)? {
+            Some((_, data)) => {
+                let mut recno = [0u8; 4];
+                recno.copy_from_slice(&data.as_slice()[..4]);
+                Ok(u32::from_ne_bytes(recno))
+            },
+            None => Err(Error::new(error::DB_NOTFOUND)),
+        }
+    }
+
+    /// Are this cursor and `other` positioned on the same record?
+    ///
+    /// Both cursors must be open on the same database handle and positioned. Requires
+    /// Berkeley DB 5.3.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// db.put(None, b"a", b"1", libdb::DB_NONE).unwrap();
+    /// db.put(None, b"b", b"2", libdb::DB_NONE).unwrap();
+    ///
+    /// let mut left = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// let mut right = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// left.first().unwrap();
+    /// right.last().unwrap();
+    /// assert!(!left.same_position(&right).unwrap());
+    /// right.prev().unwrap();
+    /// assert!(left.same_position(&right).unwrap());
+    /// ```
+    #[cfg(feature = "v5_3")]
+    pub fn same_position(&self, other: &Cursor) -> Result<bool, Error> {
+        if !ptr::eq(self.db, other.db) {
+            return Err(Error::new(libc::EINVAL));
+        }
+        self.db.check_generation()?;
+        let mut result: i32 = 0;
+        unsafe {
+            match ffi_call!(self.dbc, cmp, other.dbc, &mut result, 0) {
+                0 => Ok(result == 0),
+                e => Err(self.db.error(e))
+            }
+        }
+    }
+
+    /// Change the cache priority of the pages the cursor reads.
+    ///
+    /// A large background scan can lower its priority so the pages it reads are evicted
+    /// before the application's working set.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// assert!(cursor.set_priority(libdb::CachePriority::VeryLow).is_ok());
+    /// ```
+    pub fn set_priority(&mut self, priority: CachePriority) -> Result<(), Error> {
+        self.db.check_generation()?;
+        unsafe {
+            match ffi_call!(self.dbc, set_priority, db_ffi::DB_CACHE_PRIORITY::from(priority)) {
+                0 => Ok(()),
+                e => Err(self.db.error(e))
+            }
+        }
+    }
+
+    /// Close the cursor, releasing its locks.
+    ///
+    /// Dropping the cursor closes it too, but cannot report failures such as a deadlock
+    /// detected while releasing the locks.
+    ///
+    /// # Examples
+    /// ```
+    /// # let db = libdb::DatabaseBuilder::new()
+    /// #    .flags(libdb::DB_CREATE)
+    /// #    .open()
+    /// #    .unwrap();
+    /// let cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    /// assert!(cursor.close().is_ok());
+    /// ```
+    pub fn close(mut self) -> Result<(), Error> {
+        unsafe {
+            let ret = ffi_call!(self.dbc, close);
+            self.dbc = ptr::null_mut();
+            match ret {
+                0 => Ok(()),
+                e => Err(self.db.error(e))
+            }
+        }
+    }
+
+    /// Move the cursor to the smallest duplicate of `key` greater than or equal to `data`.
+    fn get_both_range(&mut self, key: &[u8], data: &[u8]) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        self.get(Some(key), Some(data), db_ffi::DB_GET_BOTH_RANGE)
+    }
+
+    /// Delete the record under the cursor.
+    fn del(&mut self) -> Result<(), Error> {
+        self.db.check_generation()?;
+        self.db.check_writable()?;
+        unsafe {
+            match ffi_call!(self.dbc, del, 0) {
+                0 => Ok(()),
+                e => Err(self.db.error(e))
+            }
+        }
+    }
+
+    /// Helper which runs DBC->put.
+    fn put(&mut self, key: &[u8], data: &[u8], flags: u32) -> Result<(), Error> {
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = key.as_ptr() as *mut ::std::os::raw::c_void;
+        key_dbt.size = key.len() as u32;
+
+        // Recno databases return the new record number in the key for DB_BEFORE and
+        // DB_AFTER, so give libdb somewhere of its own to write it.
+        let mut recno: u32 = 0;
+        if flags == db_ffi::DB_BEFORE || flags == db_ffi::DB_AFTER {
+            key_dbt.data = &mut recno as *mut u32 as *mut ::std::os::raw::c_void;
+            key_dbt.size = 0;
+            key_dbt.ulen = 4;
+            key_dbt.flags = db_ffi::DB_DBT_USERMEM;
+        }
+
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.data = data.as_ptr() as *mut ::std::os::raw::c_void;
+        data_dbt.size = data.len() as u32;
+
+        self.db.check_generation()?;
+        self.db.check_writable()?;
+        self.db.check_size(key, Some(data.len()))?;
+        unsafe {
+            match ffi_call!(self.dbc, put, &mut key_dbt, &mut data_dbt, flags) {
+                0 => Ok(()),
+                e => Err(self.db.error(e))
+            }
+        }
+    }
+
+    /// Helper which runs DBC->get, optionally positioning on a caller-supplied key and data.
+    fn get(&mut self, key: Option<&[u8]>, data: Option<&[u8]>, flags: u32) -> Result<Option<(DBT<'a>, DBT<'a>)>, Error> {
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.flags = db_ffi::DB_DBT_MALLOC;
+        if let Some(key) = key {
+            key_dbt.data = key.as_ptr() as *mut ::std::os::raw::c_void;
+            key_dbt.size = key.len() as u32;
+        }
+        let key_ptr = key_dbt.data;
+
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.flags = db_ffi::DB_DBT_MALLOC;
+        if let Some(data) = data {
+            data_dbt.data = data.as_ptr() as *mut ::std::os::raw::c_void;
+            data_dbt.size = data.len() as u32;
+        }
+        let data_ptr = data_dbt.data;
+
+        self.db.check_generation()?;
+        unsafe {
+            match ffi_call!(self.dbc, get, &mut key_dbt, &mut data_dbt, flags | self.read_flags) {
+                0 => {
+                    // libdb leaves the key and data alone when they matched the caller's exactly.
+                    if !key_ptr.is_null() && key_dbt.data == key_ptr {
+                        key_dbt.data = malloc_copy(key_ptr, key_dbt.size);
+                    }
+                    if !data_ptr.is_null() && data_dbt.data == data_ptr {
+                        data_dbt.data = malloc_copy(data_ptr, data_dbt.size);
+                    }
+                    Ok(Some((DBT::from(key_dbt), DBT::from(data_dbt))))
+                },
+                error::DB_NOTFOUND => Ok(None),
+                e => Err(self.db.error(e))
+            }
         }
     }
 }
 
-/// `DatabaseBuilder` is used to configure and open a database.
-pub struct DatabaseBuilder<'a> {
-    // DatabaseBuilder must not outlive its environment.
-    //_env: std::marker::PhantomData<&'a Environment>,
-    env: Option<Environment>,
+impl<'a> Drop for Cursor<'a> {
+    fn drop(&mut self) {
+        if !self.dbc.is_null() {
+            unsafe {
+                ffi_call!(self.dbc, close);
+            }
+        }
+        if let Some(env) = self.db.env.as_ref() {
+            env.resources.cursors.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// A `WriteCursor` is a `Cursor` which may also write to the database.
+///
+/// It dereferences to `Cursor` for reading and positioning.
+pub struct WriteCursor<'a> {
+    cursor: Cursor<'a>,
+}
+
+impl<'a> WriteCursor<'a> {
+    /// Store a key/data pair, leaving the cursor on it.
+    pub fn put(&mut self, key: &[u8], data: &[u8]) -> Result<(), Error> {
+        self.cursor.put(key, data, db_ffi::DB_KEYLAST)
+    }
+
+    /// Store a key/data pair at `position`, leaving the cursor on it.
+    ///
+    /// The key is only used by `KeyFirst` and `KeyLast`; the other positions are relative
+    /// to the record under the cursor. `Before` and `After` need a database with unsorted
+    /// duplicates (or a Recno database with `DB_RENUMBER`), and `Current` must not change
+    /// the sort order of a sorted duplicate.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .db_flags(libdb::DB_DUP)
+    ///     .flags(libdb::DB_CREATE)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let mut cursor = db.write_cursor(None, libdb::DB_NONE).unwrap();
+    /// cursor.put_at(b"key", b"b", libdb::CursorPut::KeyLast).unwrap();
+    /// cursor.put_at(b"key", b"a", libdb::CursorPut::Before).unwrap();
+    /// cursor.put_at(b"key", b"c", libdb::CursorPut::KeyLast).unwrap();
+    ///
+    /// assert_eq!(b"a", cursor.first().unwrap().unwrap().1.as_slice());
+    /// cursor.put_at(b"key", b"A", libdb::CursorPut::Current).unwrap();
+    /// assert_eq!(b"A", cursor.current().unwrap().unwrap().1.as_slice());
+    /// assert_eq!(b"b", cursor.next().unwrap().unwrap().1.as_slice());
+    /// ```
+    pub fn put_at(&mut self, key: &[u8], data: &[u8], position: CursorPut) -> Result<(), Error> {
+        self.cursor.put(key, data, position as u32)
+    }
+
+    /// Delete the record under the cursor.
+    ///
+    /// The cursor stays where the record was, so `next` and `prev` carry on from there and
+    /// a scan can delete records as it finds them.
+    pub fn del(&mut self) -> Result<(), Error> {
+        self.cursor.del()
+    }
+
+    /// Close the cursor, releasing its locks. See `Cursor::close`.
+    pub fn close(self) -> Result<(), Error> {
+        self.cursor.close()
+    }
+}
+
+impl<'a> Deref for WriteCursor<'a> {
+    type Target = Cursor<'a>;
+
+    fn deref(&self) -> &Cursor<'a> {
+        &self.cursor
+    }
+}
+
+impl<'a> DerefMut for WriteCursor<'a> {
+    fn deref_mut(&mut self) -> &mut Cursor<'a> {
+        &mut self.cursor
+    }
+}
+
+/// An iterator over the records in a database, created by `Db::iter`.
+///
+/// Iterating from the back (e.g. with `rev`) walks the records with a second cursor, and
+/// the iterator ends where the two cursors meet. In a database with unsorted duplicates,
+/// identical key/data pairs can make the ends meet early.
+pub struct Iter<'a> {
+    front: Cursor<'a>,
+    // Opened by the first call to `next_back`.
+    back: Option<Cursor<'a>>,
+    // The last record returned from each end, to tell when the ends meet.
+    front_last: Option<(DBT<'a>, DBT<'a>)>,
+    back_last: Option<(DBT<'a>, DBT<'a>)>,
+    done: bool,
+}
+
+impl<'a> Iter<'a> {
+    /// Return the record read from one end, unless it has already been returned from the
+    /// other.
+    fn step(&mut self, ret: Result<Option<(DBT<'a>, DBT<'a>)>, Error>, from_back: bool) -> Option<Result<(Vec<u8>, Vec<u8>), Error>> {
+        match ret {
+            Ok(Some(record)) => {
+                let other = if from_back { &self.front_last } else { &self.back_last };
+                let met = other.as_ref().map_or(false, |other| {
+                    other.0.as_slice() == record.0.as_slice() && other.1.as_slice() == record.1.as_slice()
+                });
+                if met {
+                    self.done = true;
+                    return None;
+                }
+
+                let item = (record.0.to_vec(), record.1.to_vec());
+                if from_back {
+                    self.back_last = Some(record);
+                } else {
+                    self.front_last = Some(record);
+                }
+                Some(Ok(item))
+            },
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let ret = self.front.next();
+        self.step(ret, false)
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.back.is_none() {
+            match self.front.dup() {
+                Ok(cursor) => self.back = Some(cursor),
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                },
+            }
+        }
+
+        let ret = {
+            let back = self.back.as_mut().unwrap();
+            if self.back_last.is_none() { back.last() } else { back.prev() }
+        };
+        self.step(ret, true)
+    }
+}
+
+/// An iterator over the values stored under one key, created by `Db::duplicates`.
+pub struct Duplicates<'a> {
+    cursor: Cursor<'a>,
+    key: Vec<u8>,
+    started: bool,
+    done: bool,
+}
+
+impl<'a> Iterator for Duplicates<'a> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let ret = if self.started {
+            self.cursor.next_dup()
+        } else {
+            self.started = true;
+            self.cursor.set(&self.key)
+        };
+        match ret {
+            Ok(Some((_, data))) => Some(Ok(data.to_vec())),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// A `BulkWriter` packs key/data pairs into a `DB_MULTIPLE_KEY` buffer and stores them
+/// with a single `DB->put` when the buffer fills.
+///
+/// Pairs still buffered when the writer is dropped are stored, but any error doing so is
+/// lost; call `finish` to see it. Storing fails with `EINVAL` unless the database is a
+/// Btree or Hash: libdb reads the buffer of a Recno or Queue database as record numbers.
+pub struct BulkWriter<'a> {
+    db: &'a Db,
     txn: Option<&'a Transaction>,
-    file: Option<CString>,
-    name: Option<CString>,
-    flags: Flags,
-    mode: i32,
-    db_type: DbType,
+    buffer_size: usize,
+    // The keys and data, followed in the buffer handed to libdb by `offsets` in reverse.
+    data: Vec<u8>,
+    // The key offset, key length, data offset and data length of each pair.
+    offsets: Vec<u32>,
+    written: usize,
 }
 
-impl<'a> DatabaseBuilder<'a> {
-    /// Create a new DatabaseBuilder.
-    pub fn new() -> DatabaseBuilder<'a> {
-        DatabaseBuilder {
-            env: None,
-            txn: None,
-            file: None,
-            name: None,
-            flags: DB_NONE,
-            mode: 0,
-            db_type: DbType::BTree,
+impl<'a> BulkWriter<'a> {
+    /// Buffer a key/data pair, storing the buffered pairs first if it would not fit.
+    ///
+    /// A pair larger than the buffer size is stored in a batch of its own.
+    pub fn put(&mut self, key: &[u8], data: &[u8]) -> Result<(), Error> {
+        self.db.check_writable()?;
+        self.db.check_size(key, Some(data.len()))?;
+        if !self.offsets.is_empty() && self.packed_len(1, key.len() + data.len()) > self.buffer_size {
+            self.flush()?;
         }
+
+        let key_offset = self.data.len() as u32;
+        self.data.extend_from_slice(key);
+        let data_offset = self.data.len() as u32;
+        self.data.extend_from_slice(data);
+        self.offsets.extend_from_slice(&[key_offset, key.len() as u32, data_offset, data.len() as u32]);
+        Ok(())
     }
 
-    /// Open the database within an environment.
-    pub fn environment(mut self, env: &Environment) -> Self {
-        self.env = Some(env.clone());
+    /// Store the buffered pairs.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.offsets.is_empty() {
+            return Ok(());
+        }
+        self.db.check_keyed()?;
+
+        // The buffer is built from u32s so the offsets at its end are aligned.
+        let len = self.packed_len(0, 0);
+        let mut buffer = vec![0u32; len / 4];
+        unsafe {
+            ptr::copy_nonoverlapping(self.data.as_ptr(), buffer.as_mut_ptr() as *mut u8, self.data.len());
+        }
+        // The offsets run backwards from the end of the buffer, ending with a terminator.
+        let end = buffer.len() - 1;
+        for (i, offset) in self.offsets.iter().enumerate() {
+            buffer[end - i] = *offset;
+        }
+        buffer[end - self.offsets.len()] = 0xFFFFFFFF;
+
+        let mut key_dbt: db_ffi::DBT = Default::default();
+        key_dbt.data = buffer.as_mut_ptr() as *mut ::std::os::raw::c_void;
+        key_dbt.size = len as u32;
+        key_dbt.ulen = len as u32;
+        key_dbt.flags = db_ffi::DB_DBT_BULK | db_ffi::DB_DBT_USERMEM;
+        let mut data_dbt: db_ffi::DBT = Default::default();
+
+        self.db.check_generation()?;
+        unsafe {
+            let ret = ffi_call!(self.db.db, put, unwrap_txn_ptr(self.txn), &mut key_dbt, &mut data_dbt, DB_MULTIPLE_KEY.bits());
+            if ret != 0 {
+                return Err(self.db.error(ret));
+            }
+        }
+        self.written += self.offsets.len() / 4;
+        self.data.clear();
+        self.offsets.clear();
+        Ok(())
+    }
+
+    /// Store the buffered pairs, returning the number of pairs written by the writer.
+    pub fn finish(mut self) -> Result<usize, Error> {
+        self.flush()?;
+        Ok(self.written)
+    }
+
+    /// The size of the buffer holding the buffered pairs plus `pairs` more pairs of `bytes`
+    /// bytes in total, rounded up to a whole number of u32s.
+    fn packed_len(&self, pairs: usize, bytes: usize) -> usize {
+        let pairs = self.offsets.len() / 4 + pairs;
+        let data = (self.data.len() + bytes + 3) / 4 * 4;
+        data + (pairs * 4 + 1) * 4
+    }
+}
+
+impl<'a> Drop for BulkWriter<'a> {
+    fn drop(&mut self) {
+        // Nothing can be done with an error here; `finish` reports it.
+        let _ = self.flush();
+    }
+}
+
+/// A `BulkReader` reads records in key order into a `DB_MULTIPLE_KEY` buffer, many records
+/// per call into libdb.
+pub struct BulkReader<'a> {
+    cursor: Cursor<'a>,
+    // Built from u32s so the offsets at its end are aligned.
+    buffer: Vec<u32>,
+    done: bool,
+}
+
+impl<'a> BulkReader<'a> {
+    /// Read the next buffer of records, returning `None` after the last record.
+    ///
+    /// The records borrow the reader's buffer, which is reused by the next call.
+    pub fn next_batch<'b>(&'b mut self) -> Result<Option<BulkBatch<'b>>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+        self.cursor.db.check_generation()?;
+        loop {
+            let mut key_dbt: db_ffi::DBT = Default::default();
+            let mut data_dbt: db_ffi::DBT = Default::default();
+            data_dbt.data = self.buffer.as_mut_ptr() as *mut ::std::os::raw::c_void;
+            data_dbt.ulen = (self.buffer.len() * 4) as u32;
+            data_dbt.flags = db_ffi::DB_DBT_USERMEM;
+
+            let flags = db_ffi::DB_NEXT | DB_MULTIPLE_KEY.bits() | self.cursor.read_flags;
+            match unsafe { ffi_call!(self.cursor.dbc, get, &mut key_dbt, &mut data_dbt, flags) } {
+                0 => return Ok(Some(BulkBatch { buffer: &self.buffer, index: 0 })),
+                error::DB_NOTFOUND => {
+                    self.done = true;
+                    return Ok(None);
+                },
+                // The next record alone is larger than the buffer, and libdb has said how
+                // large it needs to be.
+                error::DB_BUFFER_SMALL => self.buffer = vec![0u32; bulk_buffer_len(data_dbt.size as usize)],
+                e => return Err(self.cursor.db.error(e)),
+            }
+        }
+    }
+}
+
+/// The records read into a `BulkReader`'s buffer by one call to `next_batch`.
+pub struct BulkBatch<'b> {
+    buffer: &'b [u32],
+    // The number of records already returned.
+    index: usize,
+}
+
+impl<'b> Iterator for BulkBatch<'b> {
+    type Item = (&'b [u8], &'b [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Each record's key offset, key length, data offset and data length run backwards
+        // from the end of the buffer, ending with a terminator.
+        let end = self.buffer.len() - 1 - self.index * 4;
+        let key_offset = self.buffer[end];
+        if key_offset == 0xFFFFFFFF {
+            return None;
+        }
+        self.index += 1;
+
+        let bytes = unsafe {
+            slice::from_raw_parts(self.buffer.as_ptr() as *const u8, self.buffer.len() * 4)
+        };
+        let key = &bytes[key_offset as usize..(key_offset + self.buffer[end - 1]) as usize];
+        let data_offset = self.buffer[end - 2];
+        let data = &bytes[data_offset as usize..(data_offset + self.buffer[end - 3]) as usize];
+        Some((key, data))
+    }
+}
+
+/// The number of u32s in a bulk read buffer of at least `size` bytes, which libdb requires
+/// to be a multiple of 1KB.
+fn bulk_buffer_len(size: usize) -> usize {
+    (size.max(1) + 1023) / 1024 * 256
+}
+
+/// A cursor over the records of an environment's log, created by `Env::log_cursor`.
+///
+/// Records are returned with their LSN as the raw bytes libdb logged, for tools such as
+/// change data capture which decode them.
+pub struct LogCursor<'a> {
+    logc: *mut db_ffi::DB_LOGC,
+    // The cursor must not outlive the environment.
+    _env: PhantomData<&'a Env>,
+}
+
+impl<'a> LogCursor<'a> {
+    /// Move to the first record in the log and return it.
+    pub fn first(&mut self) -> Result<Option<(Lsn, DBT<'a>)>, Error> {
+        self.get(Lsn::default(), db_ffi::DB_FIRST)
+    }
+
+    /// Move to the last record in the log and return it.
+    pub fn last(&mut self) -> Result<Option<(Lsn, DBT<'a>)>, Error> {
+        self.get(Lsn::default(), db_ffi::DB_LAST)
+    }
+
+    /// Move to the next record and return it.
+    ///
+    /// A new cursor moves to the first record. Returns `None` after the last record.
+    pub fn next(&mut self) -> Result<Option<(Lsn, DBT<'a>)>, Error> {
+        self.get(Lsn::default(), db_ffi::DB_NEXT)
+    }
+
+    /// Move to the previous record and return it.
+    ///
+    /// A new cursor moves to the last record. Returns `None` before the first record.
+    pub fn prev(&mut self) -> Result<Option<(Lsn, DBT<'a>)>, Error> {
+        self.get(Lsn::default(), db_ffi::DB_PREV)
+    }
+
+    /// Return the record under the cursor without moving it.
+    pub fn current(&mut self) -> Result<Option<(Lsn, DBT<'a>)>, Error> {
+        self.get(Lsn::default(), db_ffi::DB_CURRENT)
+    }
+
+    /// Move to the record at `lsn` and return it.
+    ///
+    /// Fails if `lsn` is not the position of a record in the log.
+    pub fn set(&mut self, lsn: Lsn) -> Result<Option<(Lsn, DBT<'a>)>, Error> {
+        self.get(lsn, db_ffi::DB_SET)
+    }
+
+    /// Close the cursor.
+    pub fn close(mut self) -> Result<(), Error> {
+        let logc = mem::replace(&mut self.logc, ptr::null_mut());
+        unsafe {
+            check(ffi_call!(logc, close, 0))
+        }
+    }
+
+    /// Run a `DB_LOGC->get`, copying out the record it returns.
+    fn get(&mut self, lsn: Lsn, flags: u32) -> Result<Option<(Lsn, DBT<'a>)>, Error> {
+        let mut lsn = db_ffi::DB_LSN::from(lsn);
+        let mut data_dbt: db_ffi::DBT = Default::default();
+        data_dbt.flags = db_ffi::DB_DBT_MALLOC;
+        unsafe {
+            match ffi_call!(self.logc, get, &mut lsn, &mut data_dbt, flags) {
+                0 => Ok(Some((Lsn::from(lsn), DBT::from(data_dbt)))),
+                error::DB_NOTFOUND => Ok(None),
+                e => Err(Error::new(e)),
+            }
+        }
+    }
+}
+
+impl<'a> Drop for LogCursor<'a> {
+    fn drop(&mut self) {
+        if !self.logc.is_null() {
+            unsafe {
+                // Nothing needs to be done if this fails...
+                ffi_call!(self.logc, close, 0);
+            }
+        }
+    }
+}
+
+/// The length of the global transaction IDs used by `Transaction::prepare`.
+pub const GID_SIZE: usize = db_ffi::DB_GID_SIZE as usize;
+
+/// A token identifying a committed transaction, returned by `Transaction::commit_with_token`.
+///
+/// The token can be sent to another site in a replication group as bytes, so it can wait
+/// with `Env::txn_applied` until the commit has reached it.
+#[cfg(feature = "v5_3")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommitToken {
+    buf: [u8; CommitToken::SIZE],
+}
+
+#[cfg(feature = "v5_3")]
+impl CommitToken {
+    /// The length of a token in bytes.
+    pub const SIZE: usize = db_ffi::DB_TXN_TOKEN_SIZE as usize;
+
+    /// Rebuild a token from the bytes returned by `as_bytes`.
+    ///
+    /// Fails with `EINVAL` if `bytes` is not `CommitToken::SIZE` long.
+    pub fn from_bytes(bytes: &[u8]) -> Result<CommitToken, Error> {
+        if bytes.len() != CommitToken::SIZE {
+            return Err(Error::new(libc::EINVAL));
+        }
+        let mut token = CommitToken { buf: [0; CommitToken::SIZE] };
+        token.buf.copy_from_slice(bytes);
+        Ok(token)
+    }
+
+    /// The token's bytes, for sending to another site.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+/// The `Transaction` object is the handle for a transaction.
+pub struct Transaction {
+    txn_ptr: *mut db_ffi::DB_TXN,
+    env_ptr: *mut db_ffi::DB_ENV,
+    resources: Arc<Resources>,
+    // A Concurrent Data Store group, which cannot be aborted.
+    cds_group: bool,
+}
+
+/// The options a transaction is begun with, as taken by `Env::txn`.
+///
+/// Each option replaces any which conflicts with it, so only valid combinations can be
+/// built. Options which are not set are inherited from the environment.
+///
+/// # Examples
+/// ```
+/// let env = libdb::EnvironmentBuilder::new()
+///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+///     .open()
+///     .unwrap();
+/// let flags = libdb::TxnFlags::new().no_wait().read_committed().no_sync();
+/// let txn = env.txn(None, flags).unwrap();
+/// txn.commit(libdb::CommitType::Inherit).unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TxnFlags {
+    wait: Flags,
+    isolation: Flags,
+    sync: Flags,
+}
+
+impl TxnFlags {
+    /// Options which inherit everything from the environment.
+    pub fn new() -> TxnFlags {
+        TxnFlags { wait: DB_NONE, isolation: DB_NONE, sync: DB_NONE }
+    }
+
+    /// Fail with `DB_LOCK_NOTGRANTED` rather than wait for a lock.
+    pub fn no_wait(mut self) -> Self {
+        self.wait = DB_TXN_NOWAIT;
         self
     }
 
-    /// Open the database within a transaction.
-    pub fn transaction(mut self, txn: &'a Transaction) -> Self {
-        self.txn = Some(txn);
+    /// Wait for locks, even if the environment is configured not to.
+    pub fn wait(mut self) -> Self {
+        self.wait = DB_TXN_WAIT;
         self
     }
 
-    /// Specify the database file.
-    pub fn file<P: AsRef<Path>>(mut self, file: P) -> Self {
-        self.file = Some(CString::new(file.as_ref().to_str().unwrap()).unwrap());
+    /// Release read locks as soon as the read is done (degree 2 isolation).
+    pub fn read_committed(mut self) -> Self {
+        self.isolation = DB_READ_COMMITTED;
         self
     }
 
-    /// Specify the database name.
-    pub fn name(mut self, name: &str) -> Self {
-        self.name = Some(CString::new(name).unwrap());
+    /// Read modified data which has not been committed yet (degree 1 isolation).
+    pub fn read_uncommitted(mut self) -> Self {
+        self.isolation = DB_READ_UNCOMMITTED;
         self
     }
 
-    /// Set the database flags.
-    pub fn flags(mut self, flags: Flags) -> Self {
-        self.flags = flags;
+    /// Read a snapshot of databases opened with `DatabaseBuilder::multiversion`, without
+    /// taking read locks.
+    pub fn snapshot(mut self) -> Self {
+        self.isolation = DB_TXN_SNAPSHOT;
         self
     }
 
-    /// Set the file mode.
-    pub fn mode(mut self, mode: i32) -> Self {
-        self.mode = mode;
+    /// Do not flush the log when the transaction commits.
+    pub fn no_sync(mut self) -> Self {
+        self.sync = DB_TXN_NOSYNC;
         self
     }
 
-    /// Set the database type.
-    pub fn db_type(mut self, db_type: DbType) -> Self {
-        self.db_type = db_type;
+    /// Flush the log when the transaction commits, even if the environment is configured
+    /// not to.
+    pub fn sync(mut self) -> Self {
+        self.sync = DB_TXN_SYNC;
         self
     }
 
-    /// Open the database represented by the file and database.
-    ///
-    /// # Panics
-    /// Panics if libdb fails to create the DB struct (e.g. malloc error).
-    pub fn open(self) -> Result<Database, Error> {
-        // Get the DB_ENV pointer
-        let env_ptr = match self.env.as_ref() {
-            Some(env) => env.env_ptr,
-            None      => ptr::null_mut()
-        };
-        
-        // Get the file name pointer.
-        let file_ptr = match self.file.as_ref() {
-            Some(cstr) => cstr.as_ptr(),
-            None => ptr::null()
-        };
+    /// Return the options as `DB_ENV->txn_begin` flags.
+    pub fn bits(&self) -> Flags {
+        self.wait | self.isolation | self.sync
+    }
+}
 
-        // Get the database name pointer.
-        let database_ptr = match self.name.as_ref() {
-            Some(cstr) => cstr.as_ptr(),
-            None => ptr::null()
-        };
+impl Default for TxnFlags {
+    fn default() -> TxnFlags {
+        TxnFlags::new()
+    }
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, Debug)]
+pub enum CommitType {
+    /// Inherit the commit mode from the transaction or the environment.
+    Inherit = 0,
+    /// Do not synchronously flush the log.
+    NoSync = db_ffi::DB_TXN_NOSYNC,
+    /// Synchronously flush the log.
+    Sync = db_ffi::DB_TXN_SYNC,
+    /// Write the log to the operating system but do not flush it to disk. The commit
+    /// survives an application crash but not a system crash.
+    WriteNoSync = db_ffi::DB_TXN_WRITE_NOSYNC,
+}
 
-        let dbtype = db_ffi::DBTYPE::from(self.db_type);
+/// The timeouts which can be set on a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// How long the transaction may wait for any one lock.
+    Lock,
+    /// How long the transaction may run in total.
+    Txn,
+}
 
+impl Transaction {
+    /// Begin a transaction, counting it in the environment's resources.
+    fn begin(env_ptr: *mut db_ffi::DB_ENV, resources: &Arc<Resources>, parent: *mut db_ffi::DB_TXN, flags: TxnFlags) -> Result<Transaction, Error> {
         unsafe {
-            // Create the DB struct
-            let mut db: *mut db_ffi::DB = ptr::null_mut();
-            let ret = db_ffi::db_create(&mut db, env_ptr, 0);
-            if ret != 0 {
-                panic!("Could not instantiate DB. errno = {}", ret);
-            }
-
-            // Open the database
-            let ret = ((*db).open.unwrap())(db, unwrap_txn_ptr(self.txn), file_ptr, database_ptr, dbtype, self.flags.bits(), self.mode);
-            match ret {
-                0 => Ok(Arc::new(Db { env: self.env, db: db })),
-                e => {
-                    ((*db).close.unwrap())(db, 0);
-                    Err(Error::new(e))
+            let mut txn_ptr: *mut db_ffi::DB_TXN = ptr::null_mut();
+            match ffi_call!(env_ptr, txn_begin, parent, &mut txn_ptr, flags.bits().bits()) {
+                0 => {
+                    resources.transactions.fetch_add(1, Ordering::SeqCst);
+                    Ok(Transaction {
+                        txn_ptr: txn_ptr,
+                        env_ptr: env_ptr,
+                        resources: resources.clone(),
+                        cds_group: false,
+                    })
                 },
+                e => Err(Error::new(e)),
             }
         }
     }
-}
 
-/// `Database` is the handle for a single Berkeley DB database.
-///
-/// # Examples
-/// ```
-/// let ret = libdb::DatabaseBuilder::new()
-///     .flags(libdb::DB_CREATE)
-///     .open();
-/// assert!(ret.is_ok())
-/// ```
-pub struct Db {
-    env: Option<Environment>,
-    db: *mut db_ffi::DB,
-}
-
-impl Db {
-    /// Get a key/data pair from the database.
+    /// Set a savepoint in the transaction.
     ///
-    /// # Examples
+    /// Operations made through the savepoint run in a child transaction. Rolling back the
+    /// savepoint undoes them while leaving the rest of the transaction intact; releasing it
+    /// makes them part of this transaction. A savepoint which is dropped is rolled back.
+    /// The transaction cannot be used while a savepoint is set.
     ///
-    /// # Record Found
+    /// # Examples
     /// ```
-    /// # use std::str;
-    /// # let db = libdb::DatabaseBuilder::new()
-    /// #    .flags(libdb::DB_CREATE)
-    /// #    .open()
-    /// #    .unwrap();
-    /// // Note: BDB requires that the key be mutable.
-    /// let mut key   = String::from("key").into_bytes();
-    /// let mut value = String::from("value").into_bytes();
-    /// assert!(db.put(None, key.as_mut_slice(), value.as_mut_slice(), libdb::DB_NONE).is_ok());
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+    ///     .open()
+    ///     .unwrap();
     ///
-    /// let ret = db.get(None, key.as_mut_slice(), libdb::DB_NONE);
-    /// assert!(ret.is_ok());
-    /// assert_eq!("value", str::from_utf8(ret.ok().unwrap().unwrap().as_slice()).unwrap());
+    /// let mut txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// let key   = String::from("key").into_bytes();
+    /// let value = String::from("value").into_bytes();
+    /// {
+    ///     let savepoint = txn.savepoint().unwrap();
+    ///     db.put(Some(&savepoint), key.as_slice(), value.as_slice(), libdb::DB_NONE).unwrap();
+    ///     savepoint.rollback().unwrap();
+    /// }
+    /// assert!(db.get(Some(&txn), key.as_slice(), libdb::DB_NONE).unwrap().is_none());
+    /// txn.commit(libdb::CommitType::Inherit).unwrap();
     /// ```
+    pub fn savepoint<'a>(&'a mut self) -> Result<Savepoint<'a>, Error> {
+        let txn = Transaction::begin(self.env_ptr, &self.resources, self.txn_ptr, TxnFlags::new())?;
+        Ok(Savepoint { txn: txn, _parent: PhantomData })
+    }
+
+    /// Begin a child transaction nested in this one.
     ///
-    /// ## Record Not Found
+    /// The child commits into this transaction rather than the database, and aborting it
+    /// undoes only its own operations. libdb forbids operations in a parent while it has an
+    /// active child, so the child borrows this transaction mutably: it cannot be used,
+    /// committed or aborted until the child is resolved. A child which is dropped is
+    /// aborted.
+    ///
+    /// # Examples
     /// ```
-    /// # let db = libdb::DatabaseBuilder::new()
-    /// #    .flags(libdb::DB_CREATE)
-    /// #    .open()
-    /// #    .unwrap();
-    /// // Note: BDB requires that the key be mutable.
-    /// let mut key = String::from("key2").into_bytes();
-    /// let ret = db.get(None, key.as_mut_slice(), libdb::DB_NONE);
-    /// println!("{:?}", ret);
-    /// assert!(ret.is_ok());
-    /// assert!(ret.unwrap().is_none());
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let mut txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// {
+    ///     let child = txn.child(libdb::TxnFlags::new()).unwrap();
+    ///     db.put(Some(&child), b"key", b"value", libdb::DB_NONE).unwrap();
+    ///     child.commit(libdb::CommitType::Inherit).unwrap();
+    /// }
+    /// assert!(db.get(Some(&txn), b"key", libdb::DB_NONE).unwrap().is_some());
+    /// txn.commit(libdb::CommitType::Inherit).unwrap();
     /// ```
-    pub fn get(&self, txn: Option<&Transaction>, key: &mut [u8], flags: Flags) -> Result<Option<DBT>, Error> {
-        let mut key_dbt: db_ffi::DBT = Default::default();
-        key_dbt.data = key.as_mut_ptr() as *mut ::std::os::raw::c_void;
-        key_dbt.size = key.len() as u32;
+    pub fn child<'a>(&'a mut self, flags: TxnFlags) -> Result<ChildTransaction<'a>, Error> {
+        let txn = Transaction::begin(self.env_ptr, &self.resources, self.txn_ptr, flags)?;
+        Ok(ChildTransaction { txn: txn, _parent: PhantomData })
+    }
 
-        let mut data_dbt: db_ffi::DBT = Default::default();
-        data_dbt.flags = db_ffi::DB_DBT_MALLOC;
-        
+    /// Return the transaction's ID, as shown in libdb's statistics and verbose deadlock
+    /// output.
+    ///
+    /// # Examples
+    /// ```
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let first = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// let second = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// assert_ne!(first.id(), second.id());
+    /// ```
+    pub fn id(&self) -> u32 {
         unsafe {
-            match ((*self.db).get.unwrap())(self.db, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, flags.bits()) {
-                0 => Ok(Some(DBT::from(data_dbt))),
-                error::DB_NOTFOUND => Ok(None),
-                e => Err(Error::new(e))
-            }
+            ffi_call!(self.txn_ptr, id)
         }
     }
 
-    /// Store a key/data pair in the database.
+    /// Name the transaction, so it can be recognized in libdb's statistics and verbose
+    /// output.
     ///
     /// # Examples
     /// ```
-    /// # let db = libdb::DatabaseBuilder::new()
-    /// #    .flags(libdb::DB_CREATE)
-    /// #    .open()
-    /// #    .unwrap();
-    /// // Note: BDB requires that the key and value be mutable.
-    /// let mut key   = String::from("key").into_bytes();
-    /// let mut value = String::from("value").into_bytes();
-    /// let ret = db.put(None, key.as_mut_slice(), value.as_mut_slice(), libdb::DB_NONE);
-    /// assert!(ret.is_ok());
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// assert_eq!(None, txn.name().unwrap());
+    /// txn.set_name("nightly-rollup").unwrap();
+    /// assert_eq!(Some(String::from("nightly-rollup")), txn.name().unwrap());
     /// ```
-    pub fn put(&self, txn: Option<&Transaction>, key: &mut [u8], data: &mut [u8], flags: Flags) -> Result<(), Error> {
-        let mut key_dbt: db_ffi::DBT = Default::default();
-        key_dbt.data = key.as_mut_ptr() as *mut ::std::os::raw::c_void;
-        key_dbt.size = key.len() as u32;
-
-        let mut data_dbt: db_ffi::DBT = Default::default();
-        data_dbt.data = data.as_mut_ptr() as *mut ::std::os::raw::c_void;
-        data_dbt.size = data.len() as u32;
-
+    pub fn set_name(&self, name: &str) -> Result<(), Error> {
+        let name = CString::new(name).map_err(|_| Error::new(libc::EINVAL))?;
         unsafe {
-            match ((*self.db).put.unwrap())(self.db, unwrap_txn_ptr(txn), &mut key_dbt, &mut data_dbt, flags.bits()) {
-                0 => Ok(()),
-                e => Err(Error::new(e))
-            }
+            check(ffi_call!(self.txn_ptr, set_name, name.as_ptr()))
         }
     }
-}
 
-impl Drop for Db {
-    fn drop(&mut self) {
+    /// Return the name given to the transaction by `set_name`, if any.
+    pub fn name(&self) -> Result<Option<String>, Error> {
+        let mut name: *const ::std::os::raw::c_char = ptr::null();
         unsafe {
-            ((*self.db).close.unwrap())(self.db, 0);
+            check(ffi_call!(self.txn_ptr, get_name, &mut name))?;
+            Ok(cstr_to_string(name))
         }
     }
-}
 
+    /// Limit how long the transaction may wait for a lock or run, overriding the
+    /// environment's timeout.
+    ///
+    /// An operation which exceeds the timeout fails with `DB_LOCK_DEADLOCK` (or
+    /// `DB_LOCK_NOTGRANTED` in an environment with `DB_TIME_NOTGRANTED` set), after which
+    /// the transaction should be aborted. Timeouts are checked when the transaction blocks
+    /// on a lock and when deadlock detection runs. A zero timeout means none.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// txn.set_timeout(Duration::from_millis(250), libdb::TimeoutKind::Lock).unwrap();
+    /// txn.set_timeout(Duration::from_secs(5), libdb::TimeoutKind::Txn).unwrap();
+    /// ```
+    pub fn set_timeout(&self, timeout: Duration, kind: TimeoutKind) -> Result<(), Error> {
+        let which = match kind {
+            TimeoutKind::Lock => DB_SET_LOCK_TIMEOUT,
+            TimeoutKind::Txn  => DB_SET_TXN_TIMEOUT,
+        };
+        unsafe {
+            check(ffi_call!(self.txn_ptr, set_timeout, timeout_micros(timeout), which.bits()))
+        }
+    }
 
-/// The `Transaction` object is the handle for a transaction.
-pub struct Transaction {
-    txn_ptr: *mut db_ffi::DB_TXN,
-}
-
-#[repr(u32)]
-pub enum CommitType {
-    /// Inherit the commit mode from the transaction or the environment.
-    Inherit = 0,
-    /// Do not synchronously flush the log.
-    NoSync = db_ffi::DB_TXN_NOSYNC,
-    /// Synchronously flush the log.
-    Sync = db_ffi::DB_TXN_SYNC,
-}
+    /// Prepare the transaction for commit under the global transaction ID `gid`.
+    ///
+    /// This is the first phase of a two-phase commit across several environments. Once it
+    /// returns the transaction survives a crash, and is returned by `Env::txn_recover` until
+    /// it is committed or aborted.
+    pub fn prepare(&self, gid: &[u8; GID_SIZE]) -> Result<(), Error> {
+        unsafe {
+            check(ffi_call!(self.txn_ptr, prepare, gid.as_ptr() as *mut u8))
+        }
+    }
 
-impl Transaction {
     /// Complete the transaction normally.
     pub fn commit(mut self, mode: CommitType) -> Result<(), Error> {
         unsafe {
-            let ret = match ((*self.txn_ptr).commit.unwrap())(self.txn_ptr, mode as u32) {
+            let ret = match ffi_call!(self.txn_ptr, commit, mode as u32) {
                 0 => Ok(()),
                 e => Err(Error::new(e))
             };
@@ -400,12 +5788,63 @@ impl Transaction {
         }
     }
 
+    /// Complete the transaction normally, returning a token which identifies the commit.
+    ///
+    /// Pass the token to `Env::txn_applied` at any site of a replication group to wait until
+    /// the commit is durable there, so a client can read its own writes. The environment
+    /// must have logging enabled.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let env = libdb::EnvironmentBuilder::new()
+    ///     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+    ///     .open()
+    ///     .unwrap();
+    /// let db = libdb::DatabaseBuilder::new()
+    ///     .environment(&env)
+    ///     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+    ///     .open()
+    ///     .unwrap();
+    ///
+    /// let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    /// db.put(Some(&txn), b"key", b"value", libdb::DB_NONE).unwrap();
+    /// let token = txn.commit_with_token(libdb::CommitType::Inherit).unwrap();
+    ///
+    /// let token = libdb::CommitToken::from_bytes(token.as_bytes()).unwrap();
+    /// assert!(env.txn_applied(&token, Duration::from_secs(1)).unwrap());
+    /// ```
+    #[cfg(feature = "v5_3")]
+    pub fn commit_with_token(self, mode: CommitType) -> Result<CommitToken, Error> {
+        let mut token = db_ffi::DB_TXN_TOKEN { buf: [0; CommitToken::SIZE] };
+        unsafe {
+            check(ffi_call!(self.txn_ptr, set_commit_token, &mut token))?;
+        }
+        // libdb fills in the token as the transaction commits.
+        self.commit(mode)?;
+        Ok(CommitToken { buf: token.buf })
+    }
+
+    /// Release the handle of a transaction returned by `Env::txn_recover` without resolving
+    /// it.
+    ///
+    /// The transaction stays prepared, so another process (or a later recovery) can
+    /// commit or abort it. Dropping a recovered transaction aborts it instead.
+    pub fn discard(mut self) -> Result<(), Error> {
+        unsafe {
+            let ret = check(ffi_call!(self.txn_ptr, discard, 0));
+            self.txn_ptr = ptr::null_mut();
+            ret
+        }
+    }
+
     /// Termination of the transaction.
     /// 
     /// The log is played backward, and any necessary undo operations are done.
     pub fn abort(mut self) -> Result<(), Error> {
         unsafe {
-            let ret = match ((*self.txn_ptr).abort.unwrap())(self.txn_ptr) {
+            let ret = match ffi_call!(self.txn_ptr, abort) {
                 0 => Ok(()),
                 e => Err(Error::new(e))
             };
@@ -420,10 +5859,236 @@ impl Drop for Transaction {
         if ptr::null() != self.txn_ptr {
             unsafe {
                 // Nothing needs to be done if this fails...
-                ((*self.txn_ptr).abort.unwrap())(self.txn_ptr);
+                if self.cds_group {
+                    // Groups cannot be aborted, and only release their locks when ended.
+                    ffi_call!(self.txn_ptr, commit, 0);
+                } else {
+                    ffi_call!(self.txn_ptr, abort);
+                }
             }
         }
+        self.resources.transaction_finished();
+    }
+}
+
+/// A `Savepoint` marks a point within a transaction which it can be rolled back to.
+///
+/// It dereferences to the child `Transaction` its operations run in.
+pub struct Savepoint<'a> {
+    txn: Transaction,
+    // The parent transaction must not be used or completed while the savepoint is set.
+    _parent: PhantomData<&'a mut Transaction>,
+}
+
+impl<'a> Savepoint<'a> {
+    /// Undo the operations made since the savepoint was set.
+    pub fn rollback(self) -> Result<(), Error> {
+        self.txn.abort()
+    }
+
+    /// Keep the operations made since the savepoint was set as part of the transaction.
+    pub fn release(self) -> Result<(), Error> {
+        self.txn.commit(CommitType::Inherit)
+    }
+}
+
+impl<'a> Deref for Savepoint<'a> {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        &self.txn
+    }
+}
+
+/// A `ChildTransaction` is a transaction nested in a parent transaction, created by
+/// `Transaction::child`.
+///
+/// It dereferences to the `Transaction` its operations run in. The parent cannot be used
+/// while the child is active:
+///
+/// ```compile_fail
+/// # let env = libdb::EnvironmentBuilder::new()
+/// #     .flags(libdb::DB_CREATE | libdb::DB_INIT_LOCK | libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+/// #     .open()
+/// #     .unwrap();
+/// # let db = libdb::DatabaseBuilder::new()
+/// #     .environment(&env)
+/// #     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+/// #     .open()
+/// #     .unwrap();
+/// let mut txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+/// let child = txn.child(libdb::TxnFlags::new()).unwrap();
+/// db.put(Some(&txn), b"key", b"value", libdb::DB_NONE).unwrap();
+/// child.commit(libdb::CommitType::Inherit).unwrap();
+/// ```
+pub struct ChildTransaction<'a> {
+    txn: Transaction,
+    // The parent must not be used or completed while the child is active.
+    _parent: PhantomData<&'a mut Transaction>,
+}
+
+impl<'a> ChildTransaction<'a> {
+    /// Commit the child's operations into the parent transaction.
+    pub fn commit(self, mode: CommitType) -> Result<(), Error> {
+        self.txn.commit(mode)
+    }
+
+    /// Undo the child's operations, leaving the parent transaction intact.
+    pub fn abort(self) -> Result<(), Error> {
+        self.txn.abort()
+    }
+}
+
+impl<'a> Deref for ChildTransaction<'a> {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        &self.txn
+    }
+}
+
+/// A `CdsGroup` is a group of operations in a Concurrent Data Store environment, begun by
+/// `Env::cdsgroup_begin`.
+///
+/// It dereferences to the `Transaction` passed to its operations. libdb supports little
+/// else on a group: `Transaction` methods such as `prepare`, `set_name` and `set_timeout`
+/// fail with `EINVAL` when called through it. A group cannot be aborted:
+///
+/// ```compile_fail
+/// # let env = libdb::EnvironmentBuilder::new()
+/// #     .flags(libdb::DB_CREATE | libdb::DB_INIT_CDB | libdb::DB_INIT_MPOOL)
+/// #     .open()
+/// #     .unwrap();
+/// let group = env.cdsgroup_begin().unwrap();
+/// group.abort().unwrap();
+/// ```
+pub struct CdsGroup {
+    txn: Transaction,
+}
+
+impl CdsGroup {
+    /// End the group, releasing its locks.
+    pub fn commit(self) -> Result<(), Error> {
+        self.txn.commit(CommitType::Inherit)
+    }
+}
+
+impl Deref for CdsGroup {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        &self.txn
+    }
+}
+
+/// The number of bytes in the gigabytes argument of `DB_ENV->set_cachesize`.
+const GIGABYTE: u64 = 1024 * 1024 * 1024;
+
+/// Helper which converts a timeout into libdb's microseconds, saturating at the maximum.
+fn timeout_micros(timeout: Duration) -> u32 {
+    let micros = timeout.as_secs().saturating_mul(1000000) + timeout.subsec_micros() as u64;
+    micros.min(u32::max_value() as u64) as u32
+}
+
+/// Helper which copies a C string owned by libdb, if there is one.
+unsafe fn cstr_to_string(cstr: *const ::std::os::raw::c_char) -> Option<String> {
+    if cstr.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(cstr).to_string_lossy().into_owned())
+    }
+}
+
+/// Helper which converts a libdb return code into a `Result`.
+fn check(ret: i32) -> Result<(), Error> {
+    match ret {
+        0 => Ok(()),
+        e => Err(Error::new(e)),
+    }
+}
+
+/// Helper which returns the record length of a Queue or fixed-length Recno database.
+unsafe fn record_len(db: *mut db_ffi::DB) -> Result<Option<usize>, Error> {
+    let mut db_type = db_ffi::DBTYPE::DB_UNKNOWN;
+    check(ffi_call!(db, get_type, &mut db_type))?;
+    match DbType::from(db_type) {
+        DbType::Queue | DbType::Recno => {
+            let mut len = 0;
+            check(ffi_call!(db, get_re_len, &mut len))?;
+            Ok(if len > 0 { Some(len as usize) } else { None })
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Helper which borrows the bytes described by a DBT passed to a callback.
+unsafe fn dbt_slice<'b>(dbt: *const db_ffi::DBT) -> &'b [u8] {
+    if dbt.is_null() || (*dbt).size == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts((*dbt).data as *const u8, (*dbt).size as usize)
+    }
+}
+
+/// Helper which copies bytes into a callback's DB_DBT_USERMEM DBT.
+///
+/// Returns false, with the required size set, if the buffer is too small.
+unsafe fn fill_dbt(dbt: *mut db_ffi::DBT, bytes: &[u8]) -> bool {
+    (*dbt).size = bytes.len() as u32;
+    if bytes.len() > (*dbt).ulen as usize {
+        return false;
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr(), (*dbt).data as *mut u8, bytes.len());
+    true
+}
+
+/// Helper which copies a caller's buffer into memory owned by a `DBT`.
+unsafe fn malloc_copy(data: *const ::std::os::raw::c_void, size: u32) -> *mut ::std::os::raw::c_void {
+    let copy = libc::malloc(size as usize);
+    if copy.is_null() && size > 0 {
+        panic!("Could not allocate {} bytes", size);
+    }
+    ptr::copy_nonoverlapping(data as *const u8, copy as *mut u8, size as usize);
+    copy as *mut ::std::os::raw::c_void
+}
+
+/// Helper which writes a little-endian u32 to an export stream.
+fn write_u32<W: Write>(out: &mut W, value: u32) -> Result<(), Error> {
+    out.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+/// Helper which writes a length-prefixed byte string to an export stream.
+fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> Result<(), Error> {
+    write_u32(out, bytes.len() as u32)?;
+    out.write_all(bytes)?;
+    Ok(())
+}
+
+/// Helper which reads a little-endian u32 from an export stream.
+fn read_u32<R: Read>(input: &mut R) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Helper which reads a length-prefixed byte string from an export stream.
+fn read_bytes<R: Read>(input: &mut R) -> Result<Vec<u8>, Error> {
+    let len = read_u32(input)?;
+    read_exact_vec(input, len)
+}
+
+/// Helper which reads exactly `len` bytes from an export stream.
+///
+/// The buffer grows as bytes arrive rather than being sized from `len` up front, so a
+/// corrupt length cannot force a huge allocation.
+fn read_exact_vec<R: Read>(input: &mut R, len: u32) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    input.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len as usize {
+        return Err(Error::from(io::Error::from(io::ErrorKind::UnexpectedEof)));
     }
+    Ok(buf)
 }
 
 /// Helper which returns a *DB_TXN or nullptr as appropriate.
@@ -2,18 +2,65 @@
 
 use std::ffi::CStr;
 use std::fmt;
+use std::io;
+use libc;
 use libdb_sys::ffi;
 
 /// An error returned from a BDB library call.
 #[derive(Debug)]
 pub struct Error {
     errno: i32,
+    kind: ErrorKind,
+    // The underlying failure of an `ErrorKind::Io` error.
+    io: Option<io::Error>,
+}
+
+/// The kind of failure an `Error` represents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An error number returned by libdb.
+    Db,
+    /// The handle was opened before the environment was recovered and must be reopened.
+    HandleInvalidated,
+    /// Reading or writing a stream failed.
+    Io,
+    /// An export stream was written by a database configured differently from the target.
+    MetadataMismatch,
+    /// A key or value exceeds the size limit configured for the database.
+    RecordTooLarge,
+    /// A write was attempted through a read-only handle.
+    ReadOnly,
+    /// A handle was closed while it was shared or handles opened from it were still open.
+    HandlesOutstanding,
+    /// A value read through a `ChecksummedDb` does not match its checksum.
+    ChecksumMismatch,
+    /// Background services did not stop before the shutdown deadline.
+    Timeout,
 }
 
 impl Error {
     /// Create a new `Error`.
     pub fn new(errno: i32) -> Error {
-        Error { errno: errno }
+        Error { errno: errno, kind: ErrorKind::Db, io: None }
+    }
+
+    /// Create an `Error` detected by the bindings rather than returned by libdb.
+    ///
+    /// `ErrorKind::Db` errors should come from `new` with libdb's error number; made here,
+    /// they carry `EINVAL`.
+    pub fn from_kind(kind: ErrorKind) -> Error {
+        let errno = match kind {
+            ErrorKind::Db                 => libc::EINVAL,
+            ErrorKind::HandleInvalidated  => DB_RUNRECOVERY,
+            ErrorKind::Io                 => libc::EIO,
+            ErrorKind::MetadataMismatch   => libc::EINVAL,
+            ErrorKind::RecordTooLarge     => libc::EINVAL,
+            ErrorKind::ReadOnly           => libc::EACCES,
+            ErrorKind::HandlesOutstanding => libc::EBUSY,
+            ErrorKind::ChecksumMismatch   => libc::EIO,
+            ErrorKind::Timeout            => libc::ETIMEDOUT,
+        };
+        Error { errno: errno, kind: kind, io: None }
     }
 
     /// Return the error number.
@@ -21,14 +68,50 @@ impl Error {
         self.errno
     }
 
+    /// Return the kind of error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Return the I/O error an `ErrorKind::Io` error was caused by.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.io.as_ref()
+    }
+
     /// Return a `String` describing the error.
     pub fn as_string(&self) -> String {
-        unsafe {
-            CStr::from_ptr(ffi::db_strerror(self.errno)).to_string_lossy().into_owned()
+        match self.kind {
+            ErrorKind::Db => unsafe {
+                CStr::from_ptr(ffi::db_strerror(self.errno)).to_string_lossy().into_owned()
+            },
+            ErrorKind::HandleInvalidated =>
+                String::from("Handle invalidated by environment recovery; reopen it"),
+            ErrorKind::Io => match self.io {
+                Some(ref err) => err.to_string(),
+                None          => io::Error::from_raw_os_error(self.errno).to_string(),
+            },
+            ErrorKind::MetadataMismatch =>
+                String::from("Export metadata does not match the database configuration"),
+            ErrorKind::RecordTooLarge =>
+                String::from("Key or value exceeds the database's size limit"),
+            ErrorKind::ReadOnly =>
+                String::from("Write attempted through a read-only handle"),
+            ErrorKind::HandlesOutstanding =>
+                String::from("Handle closed while it is still in use"),
+            ErrorKind::ChecksumMismatch =>
+                String::from("Value does not match its checksum"),
+            ErrorKind::Timeout =>
+                String::from("Background services did not stop in time"),
         }
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error { errno: err.raw_os_error().unwrap_or(libc::EIO), kind: ErrorKind::Io, io: Some(err) }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.as_string())
@@ -0,0 +1,148 @@
+//! A small key-value abstraction implemented by databases and by an in-memory store.
+//!
+//! Code written against `KvRead`, `KvWrite` and `KvIterate` can be tested against a
+//! `MemoryStore` and deployed against a Berkeley DB `Database`. The traits cover
+//! non-transactional point reads, writes and ordered scans; anything more specific needs
+//! the database handle itself. They are enabled with the `kv` feature.
+//!
+//! `Db`'s own methods take precedence over the traits' methods of the same name, so call
+//! the traits on a database from generic code, or as e.g. `KvRead::get(&*db, key)`.
+//!
+//! # Examples
+//! ```
+//! use libdb::kv::{KvIterate, KvRead, KvWrite, MemoryStore};
+//!
+//! fn count_users<S: KvIterate>(store: &S) -> usize {
+//!     let mut count = 0;
+//!     store.scan(Some(b"user:"), &mut |key, _| {
+//!         let more = key.starts_with(b"user:");
+//!         if more {
+//!             count += 1;
+//!         }
+//!         more
+//!     }).unwrap();
+//!     count
+//! }
+//!
+//! fn add_users<S: KvWrite>(store: &S) {
+//!     store.put(b"user:1", b"alice").unwrap();
+//!     store.put(b"user:2", b"bob").unwrap();
+//!     store.put(b"zone:1", b"us").unwrap();
+//! }
+//!
+//! let memory = MemoryStore::new();
+//! add_users(&memory);
+//! assert_eq!(2, count_users(&memory));
+//! assert_eq!(Some(b"bob".to_vec()), memory.get(b"user:2").unwrap());
+//!
+//! let db = libdb::DatabaseBuilder::new()
+//!     .flags(libdb::DB_CREATE)
+//!     .open()
+//!     .unwrap();
+//! add_users(&*db);
+//! assert_eq!(2, count_users(&*db));
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use super::db::Db;
+use super::error::Error;
+use super::flags::DB_NONE;
+
+/// Point reads from a key-value store.
+pub trait KvRead {
+    /// Return the value stored under `key`, if any.
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+}
+
+/// Writes to a key-value store.
+pub trait KvWrite {
+    /// Store `value` under `key`, replacing any existing value.
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Error>;
+
+    /// Remove `key`, returning whether it was present.
+    fn delete(&self, key: &[u8]) -> Result<bool, Error>;
+}
+
+/// Ordered scans over a key-value store.
+pub trait KvIterate {
+    /// Visit the pairs in ascending bytewise key order, beginning with the first key at or
+    /// after `start`, until `visit` returns false or the pairs run out.
+    fn scan(&self, start: Option<&[u8]>, visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> Result<(), Error>;
+}
+
+impl KvRead for Db {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(Db::get(self, None, key, DB_NONE)?.map(|data| data.to_vec()))
+    }
+}
+
+impl KvWrite for Db {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        Db::put(self, None, key, value, DB_NONE)
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<bool, Error> {
+        Db::delete(self, None, key, DB_NONE)
+    }
+}
+
+impl KvIterate for Db {
+    fn scan(&self, start: Option<&[u8]>, visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> Result<(), Error> {
+        let mut cursor = self.cursor(None, DB_NONE)?;
+        let mut entry = match start {
+            Some(start) => cursor.set_range(start)?,
+            None        => cursor.next()?,
+        };
+        while let Some((key, data)) = entry {
+            if !visit(&key, &data) {
+                break;
+            }
+            entry = cursor.next()?;
+        }
+        Ok(())
+    }
+}
+
+/// An in-memory key-value store, for testing code written against the traits.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    map: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> MemoryStore {
+        Default::default()
+    }
+}
+
+impl KvRead for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.map.read().unwrap().get(key).cloned())
+    }
+}
+
+impl KvWrite for MemoryStore {
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.map.write().unwrap().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.map.write().unwrap().remove(key).is_some())
+    }
+}
+
+impl KvIterate for MemoryStore {
+    fn scan(&self, start: Option<&[u8]>, visit: &mut dyn FnMut(&[u8], &[u8]) -> bool) -> Result<(), Error> {
+        let map = self.map.read().unwrap();
+        for (key, value) in map.range(start.unwrap_or(&[]).to_vec()..) {
+            if !visit(key, value) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
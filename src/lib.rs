@@ -8,7 +8,7 @@
 //!     .open()
 //!     .unwrap();
 //!
-//! let txn = env.txn(None, libdb::DB_NONE).unwrap();
+//! let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
 //!
 //! let db = libdb::DatabaseBuilder::new()
 //!     .environment(&env)
@@ -19,11 +19,11 @@
 //!
 //! txn.commit(libdb::CommitType::Inherit).expect("Commit failed!");
 //!
-//! let mut key   = String::from("key").into_bytes();
-//! let mut value = String::from("value").into_bytes();
-//! assert!(db.put(None, key.as_mut_slice(), value.as_mut_slice(), libdb::DB_NONE).is_ok());
+//! let key   = String::from("key").into_bytes();
+//! let value = String::from("value").into_bytes();
+//! assert!(db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).is_ok());
 //!
-//! let ret = db.get(None, key.as_mut_slice(), libdb::DB_NONE);
+//! let ret = db.get(None, key.as_slice(), libdb::DB_NONE);
 //! assert!(ret.is_ok());
 //! assert_eq!("value", str::from_utf8(ret.ok().unwrap().unwrap().as_slice()).unwrap());
 //! ```
@@ -31,20 +31,64 @@
 #[macro_use] extern crate bitflags;
 extern crate libc;
 extern crate libdb_sys;
+#[cfg(feature = "ffi-trace")]
+#[macro_use] extern crate log;
 
+#[macro_use] mod macros;
+
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod checksum;
 pub mod db;
 pub mod dbt;
 pub mod error;
 pub mod flags;
+#[cfg(feature = "kv")]
+pub mod kv;
+mod service;
+pub mod stat;
+pub mod xa;
 
+pub use checksum::ChecksummedDb;
+pub use db::ArchiveFlags;
+pub use db::BulkBatch;
+pub use db::BulkReader;
+pub use db::BulkWriter;
+pub use db::CachePriority;
+pub use db::CdsGroup;
+pub use db::ChildTransaction;
+pub use db::CloseType;
+#[cfg(feature = "v5_3")]
+pub use db::CommitToken;
 pub use db::CommitType;
+pub use db::Cursor;
+pub use db::CursorPut;
 pub use db::DbType;
 pub use db::Database;
 pub use db::DatabaseBuilder;
+pub use db::DeadlockPolicy;
+pub use db::Duplicates;
+pub use db::EnvTuning;
 pub use db::Environment;
 pub use db::EnvironmentBuilder;
+pub use db::Event;
+pub use db::GID_SIZE;
+pub use db::Isolation;
+pub use db::Iter;
+pub use db::KeyRange;
+pub use db::LogCursor;
+pub use db::Lookup;
+pub use db::PutResult;
+#[cfg(feature = "v5_3")]
+pub use db::RegionObject;
+pub use db::ResourceUsage;
+pub use db::Savepoint;
+pub use db::TimeoutKind;
 pub use db::Transaction;
+pub use db::TxnFlags;
+pub use db::WriteCursor;
 pub use error::Error;
+pub use error::ErrorKind;
 pub use flags::*;
 
 
@@ -52,3 +96,13 @@ pub use flags::*;
 
 
 
+pub use stat::ActiveTxn;
+pub use stat::CacheFileStat;
+pub use stat::CacheStat;
+pub use stat::LockStat;
+pub use stat::LogStat;
+pub use stat::Lsn;
+pub use stat::MutexStat;
+pub use stat::TxnStat;
+pub use stat::TxnStatus;
+pub use xa::XaResource;
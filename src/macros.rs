@@ -0,0 +1,72 @@
+#[cfg(feature = "ffi-trace")]
+use std::fmt;
+
+/// Invoke a method from a libdb handle's function table, passing the handle as the first
+/// argument.
+///
+/// ```ignore
+/// let ret = ffi_call!(self.db, get, txn_ptr, &mut key_dbt, &mut data_dbt, flags.bits());
+/// ```
+macro_rules! ffi_call {
+    ($handle:expr, $method:ident $(, $arg:expr)*) => {{
+        let handle = $handle;
+        let mut trace = $crate::macros::Trace::new(stringify!($method));
+        let ret = ((*handle).$method.unwrap())(trace.arg(handle) $(, trace.arg($arg))*);
+        trace.finish(ret)
+    }};
+}
+
+/// Invoke a libdb function such as `db_create`.
+macro_rules! ffi_fn {
+    ($func:ident $(, $arg:expr)*) => {{
+        let mut trace = $crate::macros::Trace::new(stringify!($func));
+        let ret = ::libdb_sys::ffi::$func($(trace.arg($arg)),*);
+        trace.finish(ret)
+    }};
+}
+
+/// Records a raw libdb call, logging it at trace level once it returns.
+#[cfg(feature = "ffi-trace")]
+pub struct Trace {
+    func: &'static str,
+    args: Vec<String>,
+}
+
+#[cfg(feature = "ffi-trace")]
+impl Trace {
+    pub fn new(func: &'static str) -> Trace {
+        Trace { func: func, args: Vec::new() }
+    }
+
+    pub fn arg<T: fmt::Debug>(&mut self, arg: T) -> T {
+        self.args.push(format!("{:?}", arg));
+        arg
+    }
+
+    pub fn finish<R: fmt::Debug>(self, ret: R) -> R {
+        trace!(target: "libdb::ffi", "{}({}) = {:?}", self.func, self.args.join(", "), ret);
+        ret
+    }
+}
+
+/// Without the `ffi-trace` feature calls are not recorded.
+#[cfg(not(feature = "ffi-trace"))]
+pub struct Trace;
+
+#[cfg(not(feature = "ffi-trace"))]
+impl Trace {
+    #[inline]
+    pub fn new(_func: &'static str) -> Trace {
+        Trace
+    }
+
+    #[inline]
+    pub fn arg<T>(&mut self, arg: T) -> T {
+        arg
+    }
+
+    #[inline]
+    pub fn finish<R>(self, ret: R) -> R {
+        ret
+    }
+}
@@ -0,0 +1,154 @@
+//! Background services run against an open environment.
+//!
+//! Each service is a thread which runs its task periodically until the environment shuts
+//! it down. Services hold the environment weakly, so they never keep it open, and they run
+//! their task one last time when stopped so work such as a final checkpoint is not lost.
+//! A service's interval can be changed while it runs.
+
+use std::panic;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use libc;
+
+use super::db::Env;
+use super::error::{Error, ErrorKind};
+
+/// How often `ServiceSet::shutdown` checks whether the services have finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The background services owned by an environment.
+pub struct ServiceSet {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    threads: Mutex<Vec<Service>>,
+}
+
+/// A running service.
+struct Service {
+    name: String,
+    // Read by the service under the `stop` lock, so changes cannot be missed.
+    interval: Arc<Mutex<Duration>>,
+    thread: JoinHandle<Result<(), Error>>,
+}
+
+impl ServiceSet {
+    pub fn new() -> ServiceSet {
+        ServiceSet {
+            stop: Arc::new((Mutex::new(false), Condvar::new())),
+            threads: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Start a thread running `task` every `interval` until the set is stopped.
+    ///
+    /// A task which fails stops its service; the error is returned by `shutdown`.
+    pub fn spawn<F>(&self, env: Weak<Env>, name: &str, interval: Duration, mut task: F) -> Result<(), Error>
+        where F: FnMut(&Env) -> Result<(), Error> + Send + 'static
+    {
+        if self.is_stopped() {
+            return Err(Error::new(libc::EINVAL));
+        }
+
+        let stop = self.stop.clone();
+        let interval = Arc::new(Mutex::new(interval));
+        let current = interval.clone();
+        let thread = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let mut last_run = Instant::now();
+                loop {
+                    let stopped = {
+                        let &(ref lock, ref cvar) = &*stop;
+                        let mut stopped = lock.lock().unwrap();
+                        // A changed interval wakes the service, which then waits out the
+                        // rest of the new one.
+                        loop {
+                            let due = last_run + *current.lock().unwrap();
+                            let now = Instant::now();
+                            if *stopped || now >= due {
+                                break;
+                            }
+                            stopped = cvar.wait_timeout(stopped, due - now).unwrap().0;
+                        }
+                        *stopped
+                    };
+                    // Hold the environment only while the task runs, so it can be closed
+                    // between runs.
+                    match env.upgrade() {
+                        Some(env) => task(&env)?,
+                        None      => return Ok(()),
+                    }
+                    if stopped {
+                        return Ok(());
+                    }
+                    last_run = Instant::now();
+                }
+            })?;
+        self.threads.lock().unwrap().push(Service {
+            name: name.to_string(),
+            interval: interval,
+            thread: thread,
+        });
+        Ok(())
+    }
+
+    /// Change how often the services named `name` run.
+    ///
+    /// The new interval is counted from the end of the service's last run. Fails with
+    /// `ENOENT` if no service has that name.
+    pub fn set_interval(&self, name: &str, interval: Duration) -> Result<(), Error> {
+        let threads = self.threads.lock().unwrap();
+        let mut found = false;
+        let &(ref lock, ref cvar) = &*self.stop;
+        let _stopped = lock.lock().unwrap();
+        for service in threads.iter().filter(|service| service.name == name) {
+            *service.interval.lock().unwrap() = interval;
+            found = true;
+        }
+        if !found {
+            return Err(Error::new(libc::ENOENT));
+        }
+        cvar.notify_all();
+        Ok(())
+    }
+
+    /// Signal every service to stop without waiting for them.
+    pub fn stop(&self) {
+        let &(ref lock, ref cvar) = &*self.stop;
+        *lock.lock().unwrap() = true;
+        cvar.notify_all();
+    }
+
+    /// Has the set been stopped?
+    pub fn is_stopped(&self) -> bool {
+        *self.stop.0.lock().unwrap()
+    }
+
+    /// Stop every service and wait up to `timeout` for them to finish.
+    ///
+    /// Returns the first error a service failed with. Services which have not finished by
+    /// the deadline are left running and reported with `ErrorKind::Timeout`.
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), Error> {
+        self.stop();
+
+        let deadline = Instant::now() + timeout;
+        let mut threads = self.threads.lock().unwrap();
+        while threads.iter().any(|service| !service.thread.is_finished()) {
+            if Instant::now() >= deadline {
+                return Err(Error::from_kind(ErrorKind::Timeout));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        let mut result = Ok(());
+        for service in threads.drain(..) {
+            match service.thread.join() {
+                Ok(Err(e)) => if result.is_ok() { result = Err(e) },
+                Ok(Ok(())) => (),
+                Err(panic) => panic::resume_unwind(panic),
+            }
+        }
+        result
+    }
+}
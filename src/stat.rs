@@ -0,0 +1,482 @@
+//! Statistics reported by an environment's subsystems.
+
+use std::ffi::CStr;
+use std::slice;
+use std::time::Duration;
+
+use libdb_sys::ffi as db_ffi;
+
+/// A log sequence number: the position of a record in the environment's log.
+///
+/// LSNs order log records, so comparing two tells which was written first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lsn {
+    /// The number of the log file.
+    pub file: u32,
+    /// The offset of the record within the log file.
+    pub offset: u32,
+}
+
+impl From<db_ffi::DB_LSN> for Lsn {
+    fn from(lsn: db_ffi::DB_LSN) -> Lsn {
+        Lsn { file: lsn.file, offset: lsn.offset }
+    }
+}
+
+impl From<Lsn> for db_ffi::DB_LSN {
+    fn from(lsn: Lsn) -> db_ffi::DB_LSN {
+        db_ffi::DB_LSN { file: lsn.file, offset: lsn.offset }
+    }
+}
+
+/// The number of bytes in the megabytes counters of libdb's statistics.
+const MEGABYTE: u64 = 1024 * 1024;
+
+/// The number of bytes in the gigabytes counters of libdb's statistics.
+const GIGABYTE: u64 = 1024 * MEGABYTE;
+
+/// Transaction subsystem statistics, as returned by `Env::txn_stat`.
+#[derive(Clone, Debug)]
+pub struct TxnStat {
+    /// The LSN of the last checkpoint.
+    pub last_checkpoint: Lsn,
+    /// When the last checkpoint finished, in seconds since the epoch, or 0 if there has
+    /// been none.
+    pub last_checkpoint_time: i64,
+    /// The last transaction ID allocated.
+    pub last_txn_id: u32,
+    /// The number of transactions the region has room for.
+    pub max_txns: u32,
+    /// Transactions aborted.
+    pub aborts: u64,
+    /// Transactions begun.
+    pub begins: u64,
+    /// Transactions committed.
+    pub commits: u64,
+    /// Transactions currently active.
+    pub active: u32,
+    /// Snapshot transactions currently active.
+    pub snapshots: u32,
+    /// The most transactions active at once.
+    pub max_active: u32,
+    /// The most snapshot transactions active at once.
+    pub max_snapshots: u32,
+    /// Times a thread had to wait for the region lock.
+    pub region_wait: u64,
+    /// Times a thread took the region lock without waiting.
+    pub region_nowait: u64,
+    /// The size of the transaction region in bytes.
+    pub region_size: usize,
+    /// The active transactions.
+    pub active_txns: Vec<ActiveTxn>,
+}
+
+/// A transaction listed in `TxnStat::active_txns`.
+#[derive(Clone, Debug)]
+pub struct ActiveTxn {
+    /// The transaction's ID.
+    pub id: u32,
+    /// The parent transaction's ID, or 0 for a top-level transaction.
+    pub parent_id: u32,
+    /// The ID of the process which began the transaction.
+    pub pid: i32,
+    /// The LSN of the transaction's begin record.
+    pub lsn: Lsn,
+    /// The state of the transaction.
+    pub status: TxnStatus,
+    /// The global transaction ID given to `Transaction::prepare`, if prepared.
+    pub gid: Option<Vec<u8>>,
+    /// The name given to `Transaction::set_name`, if any.
+    pub name: Option<String>,
+}
+
+/// The state of an active transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxnStatus {
+    /// The transaction is in progress.
+    Running,
+    /// The transaction has been prepared and awaits commit or abort.
+    Prepared,
+    /// The transaction has committed and is being cleaned up.
+    Committed,
+    /// The transaction has aborted and is being cleaned up.
+    Aborted,
+}
+
+impl TxnStat {
+    /// Copy the statistics libdb returned.
+    pub(crate) unsafe fn from_raw(stat: &db_ffi::DB_TXN_STAT) -> TxnStat {
+        let active_txns = if stat.st_txnarray.is_null() {
+            &[][..]
+        } else {
+            slice::from_raw_parts(stat.st_txnarray, stat.st_nactive as usize)
+        };
+        TxnStat {
+            last_checkpoint: Lsn::from(stat.st_last_ckp),
+            last_checkpoint_time: stat.st_time_ckp as i64,
+            last_txn_id: stat.st_last_txnid,
+            max_txns: stat.st_maxtxns,
+            aborts: stat.st_naborts as u64,
+            begins: stat.st_nbegins as u64,
+            commits: stat.st_ncommits as u64,
+            active: stat.st_nactive,
+            snapshots: stat.st_nsnapshot,
+            max_active: stat.st_maxnactive,
+            max_snapshots: stat.st_maxnsnapshot,
+            region_wait: stat.st_region_wait as u64,
+            region_nowait: stat.st_region_nowait as u64,
+            region_size: stat.st_regsize as usize,
+            active_txns: active_txns.iter().map(|txn| ActiveTxn::from_raw(txn)).collect(),
+        }
+    }
+}
+
+impl ActiveTxn {
+    unsafe fn from_raw(txn: &db_ffi::DB_TXN_ACTIVE) -> ActiveTxn {
+        let status = match txn.status {
+            db_ffi::TXN_ABORTED   => TxnStatus::Aborted,
+            db_ffi::TXN_COMMITTED => TxnStatus::Committed,
+            db_ffi::TXN_PREPARED  => TxnStatus::Prepared,
+            _                     => TxnStatus::Running,
+        };
+        let name = if txn.name[0] == 0 {
+            None
+        } else {
+            Some(CStr::from_ptr(txn.name.as_ptr()).to_string_lossy().into_owned())
+        };
+        ActiveTxn {
+            id: txn.txnid,
+            parent_id: txn.parentid,
+            pid: txn.pid as i32,
+            lsn: Lsn::from(txn.lsn),
+            status: status,
+            gid: if status == TxnStatus::Prepared { Some(txn.gid.to_vec()) } else { None },
+            name: name,
+        }
+    }
+}
+
+/// Log subsystem statistics, as returned by `Env::log_stat`.
+#[derive(Clone, Debug)]
+pub struct LogStat {
+    /// The LSN the next log record will be written at.
+    pub current_lsn: Lsn,
+    /// The LSN up to which the log has been written to disk.
+    pub disk_lsn: Lsn,
+    /// The size of the in-memory log buffer in bytes.
+    pub buffer_size: u32,
+    /// The size at which the log rolls over to a new file, in bytes.
+    pub file_size: u32,
+    /// Bytes written to the log since the last checkpoint.
+    pub bytes_since_checkpoint: u64,
+    /// Bytes written to the log.
+    pub bytes_written: u64,
+    /// Records written to the log.
+    pub records: u64,
+    /// Times the log was written to disk.
+    pub writes: u64,
+    /// Times the log was written to disk because the buffer was full.
+    pub buffer_full_writes: u64,
+    /// Times the log was read from disk.
+    pub reads: u64,
+    /// Times the log was flushed to stable storage.
+    pub syncs: u64,
+    /// The most commits flushed to disk by a single log write.
+    pub max_commits_per_flush: u32,
+    /// The fewest commits flushed to disk by a single log write.
+    pub min_commits_per_flush: u32,
+    /// Times a thread had to wait for the region lock.
+    pub region_wait: u64,
+    /// Times a thread took the region lock without waiting.
+    pub region_nowait: u64,
+    /// The size of the log region in bytes.
+    pub region_size: usize,
+}
+
+impl LogStat {
+    /// Copy the statistics libdb returned.
+    pub(crate) fn from_raw(stat: &db_ffi::DB_LOG_STAT) -> LogStat {
+        LogStat {
+            current_lsn: Lsn { file: stat.st_cur_file, offset: stat.st_cur_offset },
+            disk_lsn: Lsn { file: stat.st_disk_file, offset: stat.st_disk_offset },
+            buffer_size: stat.st_lg_bsize,
+            file_size: stat.st_lg_size,
+            bytes_since_checkpoint: stat.st_wc_mbytes as u64 * MEGABYTE + stat.st_wc_bytes as u64,
+            bytes_written: stat.st_w_mbytes as u64 * MEGABYTE + stat.st_w_bytes as u64,
+            records: stat.st_record as u64,
+            writes: stat.st_wcount as u64,
+            buffer_full_writes: stat.st_wcount_fill as u64,
+            reads: stat.st_rcount as u64,
+            syncs: stat.st_scount as u64,
+            max_commits_per_flush: stat.st_maxcommitperflush,
+            min_commits_per_flush: stat.st_mincommitperflush,
+            region_wait: stat.st_region_wait as u64,
+            region_nowait: stat.st_region_nowait as u64,
+            region_size: stat.st_regsize as usize,
+        }
+    }
+}
+
+/// Lock subsystem statistics, as returned by `Env::lock_stat`.
+#[derive(Clone, Debug)]
+pub struct LockStat {
+    /// The number of locks the region has room for.
+    pub max_locks: u32,
+    /// The number of lockers the region has room for.
+    pub max_lockers: u32,
+    /// The number of lock objects the region has room for.
+    pub max_objects: u32,
+    /// The number of partitions of the lock table.
+    pub partitions: u32,
+    /// Locks currently held.
+    pub locks: u32,
+    /// The most locks held at once.
+    pub peak_locks: u32,
+    /// Lockers currently registered.
+    pub lockers: u32,
+    /// The most lockers registered at once.
+    pub peak_lockers: u32,
+    /// Objects currently locked.
+    pub objects: u32,
+    /// The most objects locked at once.
+    pub peak_objects: u32,
+    /// Locks requested.
+    pub requests: u64,
+    /// Locks released.
+    pub releases: u64,
+    /// Locks upgraded.
+    pub upgrades: u64,
+    /// Locks downgraded.
+    pub downgrades: u64,
+    /// Lock requests which conflicted with a held lock and had to wait.
+    pub conflicts: u64,
+    /// Lock requests granted without waiting.
+    pub nowaits: u64,
+    /// Deadlocks broken by the detector.
+    pub deadlocks: u64,
+    /// The environment's lock timeout, or zero if there is none.
+    pub lock_timeout: Duration,
+    /// Lock requests which failed because the lock timeout expired.
+    pub lock_timeouts: u64,
+    /// The environment's transaction timeout, or zero if there is none.
+    pub txn_timeout: Duration,
+    /// Lock requests which failed because the transaction timeout expired.
+    pub txn_timeouts: u64,
+    /// The most times a thread waited for any one lock table partition.
+    pub max_partition_wait: u64,
+    /// Times a thread had to wait for the region lock.
+    pub region_wait: u64,
+    /// Times a thread took the region lock without waiting.
+    pub region_nowait: u64,
+    /// The size of the lock region in bytes.
+    pub region_size: usize,
+}
+
+impl LockStat {
+    /// Copy the statistics libdb returned.
+    pub(crate) fn from_raw(stat: &db_ffi::DB_LOCK_STAT) -> LockStat {
+        LockStat {
+            max_locks: stat.st_maxlocks,
+            max_lockers: stat.st_maxlockers,
+            max_objects: stat.st_maxobjects,
+            partitions: stat.st_partitions,
+            locks: stat.st_nlocks,
+            peak_locks: stat.st_maxnlocks,
+            lockers: stat.st_nlockers,
+            peak_lockers: stat.st_maxnlockers,
+            objects: stat.st_nobjects,
+            peak_objects: stat.st_maxnobjects,
+            requests: stat.st_nrequests as u64,
+            releases: stat.st_nreleases as u64,
+            upgrades: stat.st_nupgrade as u64,
+            downgrades: stat.st_ndowngrade as u64,
+            conflicts: stat.st_lock_wait as u64,
+            nowaits: stat.st_lock_nowait as u64,
+            deadlocks: stat.st_ndeadlocks as u64,
+            lock_timeout: Duration::from_micros(stat.st_locktimeout as u64),
+            lock_timeouts: stat.st_nlocktimeouts as u64,
+            txn_timeout: Duration::from_micros(stat.st_txntimeout as u64),
+            txn_timeouts: stat.st_ntxntimeouts as u64,
+            max_partition_wait: stat.st_part_max_wait as u64,
+            region_wait: stat.st_region_wait as u64,
+            region_nowait: stat.st_region_nowait as u64,
+            region_size: stat.st_regsize as usize,
+        }
+    }
+}
+
+/// Mutex subsystem statistics, as returned by `Env::mutex_stat`.
+#[derive(Clone, Debug)]
+pub struct MutexStat {
+    /// The alignment of mutexes in bytes.
+    pub align: u32,
+    /// How many times a thread spins on a contended mutex before blocking.
+    pub tas_spins: u32,
+    /// Mutexes allocated.
+    pub mutexes: u32,
+    /// The most mutexes which can be allocated.
+    pub max_mutexes: u32,
+    /// Mutexes free for allocation.
+    pub free: u32,
+    /// Mutexes in use.
+    pub in_use: u32,
+    /// The most mutexes in use at once.
+    pub peak_in_use: u32,
+    /// Times a thread had to wait for the region lock.
+    pub region_wait: u64,
+    /// Times a thread took the region lock without waiting.
+    pub region_nowait: u64,
+    /// The size of the mutex region in bytes.
+    pub region_size: usize,
+}
+
+impl MutexStat {
+    /// Copy the statistics libdb returned.
+    pub(crate) fn from_raw(stat: &db_ffi::DB_MUTEX_STAT) -> MutexStat {
+        MutexStat {
+            align: stat.st_mutex_align,
+            tas_spins: stat.st_mutex_tas_spins,
+            mutexes: stat.st_mutex_cnt,
+            max_mutexes: stat.st_mutex_max,
+            free: stat.st_mutex_free,
+            in_use: stat.st_mutex_inuse,
+            peak_in_use: stat.st_mutex_inuse_max,
+            region_wait: stat.st_region_wait as u64,
+            region_nowait: stat.st_region_nowait as u64,
+            region_size: stat.st_regsize as usize,
+        }
+    }
+}
+
+/// Cache statistics, as returned by `Env::memp_stat`.
+#[derive(Clone, Debug)]
+pub struct CacheStat {
+    /// The size of the cache in bytes.
+    pub cache_size: u64,
+    /// The number of regions the cache is split into.
+    pub caches: u32,
+    /// Pages in the cache.
+    pub pages: u32,
+    /// Clean pages in the cache.
+    pub clean_pages: u32,
+    /// Dirty pages in the cache.
+    pub dirty_pages: u32,
+    /// Page requests found in the cache.
+    pub hits: u64,
+    /// Page requests not found in the cache.
+    pub misses: u64,
+    /// Pages created in the cache.
+    pub pages_created: u64,
+    /// Pages read into the cache.
+    pub pages_read: u64,
+    /// Pages written from the cache.
+    pub pages_written: u64,
+    /// Clean pages evicted from the cache.
+    pub clean_evictions: u64,
+    /// Dirty pages evicted from the cache.
+    pub dirty_evictions: u64,
+    /// Dirty pages written to keep part of the cache clean.
+    pub trickle_writes: u64,
+    /// Times a thread had to wait for the region lock.
+    pub region_wait: u64,
+    /// Times a thread took the region lock without waiting.
+    pub region_nowait: u64,
+    /// The size of the cache region in bytes.
+    pub region_size: usize,
+    /// Statistics for each file open in the cache.
+    pub files: Vec<CacheFileStat>,
+}
+
+/// Statistics for a file in the cache, listed in `CacheStat::files`.
+#[derive(Clone, Debug)]
+pub struct CacheFileStat {
+    /// The name of the file.
+    pub file: String,
+    /// The page size of the file in bytes.
+    pub page_size: u32,
+    /// Pages of the file mapped into memory.
+    pub mapped: u32,
+    /// Page requests found in the cache.
+    pub hits: u64,
+    /// Page requests not found in the cache.
+    pub misses: u64,
+    /// Pages created in the cache.
+    pub pages_created: u64,
+    /// Pages read into the cache.
+    pub pages_read: u64,
+    /// Pages written from the cache.
+    pub pages_written: u64,
+}
+
+impl CacheStat {
+    /// The fraction of page requests found in the cache, or 1 if there have been none.
+    pub fn hit_ratio(&self) -> f64 {
+        hit_ratio(self.hits, self.misses)
+    }
+
+    /// Copy the statistics libdb returned.
+    pub(crate) unsafe fn from_raw(stat: &db_ffi::DB_MPOOL_STAT, files: *mut *mut db_ffi::DB_MPOOL_FSTAT) -> CacheStat {
+        let mut file_stats = Vec::new();
+        if !files.is_null() {
+            // The list is terminated by a null pointer.
+            let mut entry = files;
+            while !(*entry).is_null() {
+                file_stats.push(CacheFileStat::from_raw(&**entry));
+                entry = entry.offset(1);
+            }
+        }
+        CacheStat {
+            cache_size: stat.st_gbytes as u64 * GIGABYTE + stat.st_bytes as u64,
+            caches: stat.st_ncache,
+            pages: stat.st_pages,
+            clean_pages: stat.st_page_clean,
+            dirty_pages: stat.st_page_dirty,
+            hits: stat.st_cache_hit as u64,
+            misses: stat.st_cache_miss as u64,
+            pages_created: stat.st_page_create as u64,
+            pages_read: stat.st_page_in as u64,
+            pages_written: stat.st_page_out as u64,
+            clean_evictions: stat.st_ro_evict as u64,
+            dirty_evictions: stat.st_rw_evict as u64,
+            trickle_writes: stat.st_page_trickle as u64,
+            region_wait: stat.st_region_wait as u64,
+            region_nowait: stat.st_region_nowait as u64,
+            region_size: stat.st_regsize as usize,
+            files: file_stats,
+        }
+    }
+}
+
+impl CacheFileStat {
+    /// The fraction of page requests found in the cache, or 1 if there have been none.
+    pub fn hit_ratio(&self) -> f64 {
+        hit_ratio(self.hits, self.misses)
+    }
+
+    unsafe fn from_raw(stat: &db_ffi::DB_MPOOL_FSTAT) -> CacheFileStat {
+        let file = if stat.file_name.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(stat.file_name).to_string_lossy().into_owned()
+        };
+        CacheFileStat {
+            file: file,
+            page_size: stat.st_pagesize,
+            mapped: stat.st_map,
+            hits: stat.st_cache_hit as u64,
+            misses: stat.st_cache_miss as u64,
+            pages_created: stat.st_page_create as u64,
+            pages_read: stat.st_page_in as u64,
+            pages_written: stat.st_page_out as u64,
+        }
+    }
+}
+
+/// Helper which computes the fraction of requests which hit.
+fn hit_ratio(hits: u64, misses: u64) -> f64 {
+    if hits + misses == 0 {
+        1.0
+    } else {
+        hits as f64 / (hits + misses) as f64
+    }
+}
@@ -0,0 +1,158 @@
+//! Enlisting an environment in global transactions run by an external transaction manager.
+//!
+//! An `XaResource` maps global transaction IDs to the environment's transactions, so a
+//! transaction manager can drive each branch through XA's start, prepare, commit and
+//! rollback calls, and resolve branches left prepared by a crash through `recover`.
+//!
+//! # Examples
+//! ```
+//! # extern crate libdb;
+//! # extern crate tempdir;
+//! # fn main() {
+//! let dir = tempdir::TempDir::new("libdb-rs").unwrap();
+//! let env = libdb::EnvironmentBuilder::new()
+//!     .home(dir.path())
+//!     .flags(libdb::DB_CREATE | libdb::DB_RECOVER | libdb::DB_INIT_LOCK |
+//!            libdb::DB_INIT_LOG | libdb::DB_INIT_MPOOL | libdb::DB_INIT_TXN)
+//!     .open()
+//!     .unwrap();
+//! let db = libdb::DatabaseBuilder::new()
+//!     .environment(&env)
+//!     .file("db")
+//!     .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+//!     .open()
+//!     .unwrap();
+//!
+//! let mut xa = libdb::XaResource::new(env.clone());
+//! {
+//!     let txn = xa.start(b"order-42").unwrap();
+//!     db.put(Some(txn), b"key", b"value", libdb::DB_NONE).unwrap();
+//! }
+//! xa.prepare(b"order-42").unwrap();
+//! xa.commit(b"order-42").unwrap();
+//!
+//! assert!(db.get(None, b"key", libdb::DB_NONE).unwrap().is_some());
+//! # }
+//! ```
+
+use std::collections::HashMap;
+
+use libc;
+
+use super::db::{CommitType, Environment, Transaction, TxnFlags, GID_SIZE};
+use super::error::Error;
+
+/// A global transaction ID, padded with zeros to `GID_SIZE` bytes.
+pub type Gid = [u8; GID_SIZE];
+
+/// An environment's participation in global transactions, driven by a transaction manager.
+///
+/// Branches are named by global transaction IDs of up to `GID_SIZE` bytes. Calls naming a
+/// branch the resource does not know fail with `ENOENT`. Branches still open when the
+/// resource is dropped are aborted, except those which are prepared: they are discarded,
+/// leaving them for `recover`.
+pub struct XaResource {
+    env: Environment,
+    branches: HashMap<Gid, Branch>,
+}
+
+struct Branch {
+    txn: Transaction,
+    prepared: bool,
+}
+
+impl XaResource {
+    /// Create a resource for the environment, which must be transactional.
+    pub fn new(env: Environment) -> XaResource {
+        XaResource { env: env, branches: HashMap::new() }
+    }
+
+    /// Begin the branch `gid`, returning the transaction to do its work in.
+    ///
+    /// Fails with `EEXIST` if the branch has already been started.
+    pub fn start<'a>(&'a mut self, gid: &[u8]) -> Result<&'a Transaction, Error> {
+        let gid = pad_gid(gid)?;
+        if self.branches.contains_key(&gid) {
+            return Err(Error::new(libc::EEXIST));
+        }
+        let txn = self.env.txn(None, TxnFlags::new())?;
+        let branch = self.branches.entry(gid).or_insert(Branch { txn: txn, prepared: false });
+        Ok(&branch.txn)
+    }
+
+    /// Return the transaction of the branch `gid`, if it is open.
+    pub fn transaction<'a>(&'a self, gid: &[u8]) -> Option<&'a Transaction> {
+        let gid = pad_gid(gid).ok()?;
+        self.branches.get(&gid).map(|branch| &branch.txn)
+    }
+
+    /// Prepare the branch `gid`, the first phase of committing it.
+    pub fn prepare(&mut self, gid: &[u8]) -> Result<(), Error> {
+        let gid = pad_gid(gid)?;
+        let branch = self.branches.get_mut(&gid).ok_or(Error::new(libc::ENOENT))?;
+        branch.txn.prepare(&gid)?;
+        branch.prepared = true;
+        Ok(())
+    }
+
+    /// Commit the branch `gid`.
+    ///
+    /// A branch which has not been prepared is committed in one phase.
+    pub fn commit(&mut self, gid: &[u8]) -> Result<(), Error> {
+        let branch = self.take(gid)?;
+        branch.txn.commit(CommitType::Inherit)
+    }
+
+    /// Roll back the branch `gid`.
+    pub fn rollback(&mut self, gid: &[u8]) -> Result<(), Error> {
+        let branch = self.take(gid)?;
+        branch.txn.abort()
+    }
+
+    /// Forget the branch `gid` without resolving it, leaving it prepared for another
+    /// process or a later `recover`.
+    pub fn forget(&mut self, gid: &[u8]) -> Result<(), Error> {
+        let branch = self.take(gid)?;
+        branch.txn.discard()
+    }
+
+    /// Find the branches which were prepared but not resolved before the environment was
+    /// last closed, returning their IDs.
+    ///
+    /// The recovered branches can then be committed, rolled back or forgotten by ID.
+    pub fn recover(&mut self) -> Result<Vec<Gid>, Error> {
+        let mut gids = Vec::new();
+        for (txn, gid) in self.env.txn_recover()? {
+            gids.push(gid);
+            self.branches.insert(gid, Branch { txn: txn, prepared: true });
+        }
+        Ok(gids)
+    }
+
+    /// Remove the branch `gid` from the resource.
+    fn take(&mut self, gid: &[u8]) -> Result<Branch, Error> {
+        let gid = pad_gid(gid)?;
+        self.branches.remove(&gid).ok_or(Error::new(libc::ENOENT))
+    }
+}
+
+impl Drop for XaResource {
+    fn drop(&mut self) {
+        for (_, branch) in self.branches.drain() {
+            if branch.prepared {
+                // Nothing needs to be done if this fails; recovery finds the branch.
+                let _ = branch.txn.discard();
+            }
+        }
+    }
+}
+
+/// Helper which pads a global transaction ID to `GID_SIZE` bytes.
+fn pad_gid(gid: &[u8]) -> Result<Gid, Error> {
+    if gid.len() > GID_SIZE {
+        return Err(Error::new(libc::EINVAL));
+    }
+    let mut padded = [0; GID_SIZE];
+    padded[..gid.len()].copy_from_slice(gid);
+    Ok(padded)
+}
@@ -11,15 +11,15 @@ fn open_close_open_test() {
 
     {
         let (env, db) = open_test_db(dbdir.path());
-        let mut key   = String::from("key").into_bytes();
-        let mut value = String::from("value").into_bytes();
-        assert!(db.put(None, key.as_mut_slice(), value.as_mut_slice(), libdb::DB_NONE).is_ok());
+        let key   = String::from("key").into_bytes();
+        let value = String::from("value").into_bytes();
+        assert!(db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).is_ok());
     }
     
     {
         let (env, db) = open_test_db(dbdir.path());
-        let mut key = String::from("key").into_bytes();
-        assert_record_eq(&db, key.as_mut_slice(), "value");
+        let key = String::from("key").into_bytes();
+        assert_record_eq(&db, key.as_slice(), "value");
     }
 }
 
@@ -28,39 +28,542 @@ fn test_transaction() {
     let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
     let (env, db) = open_test_db(dbdir.path());
 
-    let mut key   = String::from("key").into_bytes();
-    let mut value = String::from("value").into_bytes();
+    let key   = String::from("key").into_bytes();
+    let value = String::from("value").into_bytes();
 
     // Test explicit abort
     {
-        let txn = env.txn(None, libdb::DB_NONE).unwrap();
-        let ret = db.put(Some(&txn), key.as_mut_slice(), value.as_mut_slice(), libdb::DB_NONE);
+        let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+        let ret = db.put(Some(&txn), key.as_slice(), value.as_slice(), libdb::DB_NONE);
         print!("{:?}", ret);
         assert!(ret.is_ok());
         assert!(txn.abort().is_ok());
     }
 
     // Should have no record.
-    assert_norecord(&db, key.as_mut_slice());
+    assert_norecord(&db, key.as_slice());
 
     // Test abort when out of scope
     {
-        let txn = env.txn(None, libdb::DB_NONE).unwrap();
-        db.put(Some(&txn), key.as_mut_slice(), value.as_mut_slice(), libdb::DB_NONE).expect("Failed to put");
+        let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+        db.put(Some(&txn), key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
     }
 
     // Should have no record.
-    assert_norecord(&db, key.as_mut_slice());
+    assert_norecord(&db, key.as_slice());
 
     // Test commit
     {
-        let txn = env.txn(None, libdb::DB_NONE).unwrap();
-        db.put(Some(&txn), key.as_mut_slice(), value.as_mut_slice(), libdb::DB_NONE).expect("Failed to put");
+        let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+        db.put(Some(&txn), key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
         txn.commit(libdb::CommitType::Inherit).expect("Failed to commit");
     }
 
     // Should have no record.
-    assert_record_eq(&db, key.as_mut_slice(), "value");
+    assert_record_eq(&db, key.as_slice(), "value");
+}
+
+#[test]
+fn test_handle_invalidation() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    let key   = String::from("key").into_bytes();
+    let value = String::from("value").into_bytes();
+    db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
+
+    env.invalidate_handles();
+
+    match db.get(None, key.as_slice(), libdb::DB_NONE) {
+        Err(e) => assert_eq!(libdb::ErrorKind::HandleInvalidated, e.kind()),
+        Ok(_)  => panic!("Expected stale handle to be rejected"),
+    }
+    drop(db);
+
+    // A handle opened in the new generation works.
+    let db = libdb::DatabaseBuilder::new()
+        .environment(&env)
+        .file("db")
+        .open()
+        .expect("Failed to reopen");
+    assert_record_eq(&db, key.as_slice(), "value");
+}
+
+#[test]
+fn test_handle_invalidation_on_panic() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    let key        = String::from("key").into_bytes();
+    let generation = env.generation();
+
+    // Stands in for a failure which leaves the environment needing recovery.
+    env.set_flags(libdb::DB_PANIC_ENVIRONMENT, true).expect("Failed to panic environment");
+
+    match db.get(None, key.as_slice(), libdb::DB_NONE) {
+        Err(e) => assert_eq!(libdb::ErrorKind::HandleInvalidated, e.kind()),
+        Ok(_)  => panic!("Expected panicked environment to fail"),
+    }
+    assert!(env.generation() != generation);
+}
+
+#[test]
+fn test_delete_range() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    for name in &["a", "b", "c", "d", "e", "f", "g", "h"] {
+        let key   = name.to_string().into_bytes();
+        let value = String::from("value").into_bytes();
+        db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
+    }
+
+    let range = libdb::KeyRange::Between(Some(b"c"), Some(b"g"));
+    assert_eq!(4, db.delete_range(Some(&env), range, 3).expect("Failed to delete"));
+
+    for name in &["a", "b", "g", "h"] {
+        let key = name.to_string().into_bytes();
+        assert_record_eq(&db, key.as_slice(), "value");
+    }
+    for name in &["c", "d", "e", "f"] {
+        let key = name.to_string().into_bytes();
+        assert_norecord(&db, key.as_slice());
+    }
+}
+
+#[test]
+fn test_import_corrupt_stream() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    let key   = String::from("key").into_bytes();
+    let value = String::from("value").into_bytes();
+    db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
+
+    let mut dump = Vec::new();
+    db.export(None, None, &mut dump).expect("Failed to export");
+
+    // Keep the header, then claim a key of nearly 4GB which the stream does not hold.
+    let header_len = dump.len() - (4 + key.len() + 4 + value.len() + 4);
+    dump.truncate(header_len);
+    dump.extend_from_slice(&[0xFE, 0xFF, 0xFF, 0xFF, b'k']);
+
+    let copy = libdb::DatabaseBuilder::new()
+        .environment(&env)
+        .file("copy")
+        .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+        .open()
+        .expect("Failed to open DB");
+    let err = copy.import(None, None, &mut dump.as_slice()).unwrap_err();
+    assert_eq!(libdb::ErrorKind::Io, err.kind());
+    assert!(err.io_error().is_some());
+}
+
+#[test]
+fn test_resource_usage() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    {
+        let txn    = env.txn(None, libdb::TxnFlags::new()).unwrap();
+        let cursor = db.cursor(Some(&txn), libdb::DB_NONE).unwrap();
+        let usage  = env.resource_usage();
+        assert_eq!(1, usage.databases);
+        assert_eq!(1, usage.cursors);
+        assert_eq!(1, usage.transactions);
+        drop(cursor);
+    }
+
+    let usage = env.resource_usage();
+    assert_eq!(0, usage.cursors);
+    assert_eq!(0, usage.transactions);
+
+    // The database handle still shares the environment.
+    let shared = env.clone();
+    let err = env.close().unwrap_err();
+    assert_eq!(libdb::ErrorKind::HandlesOutstanding, err.kind());
+
+    drop(db);
+    assert_eq!(0, shared.resource_usage().databases);
+    shared.close().expect("Failed to close");
+}
+
+#[test]
+fn test_close_with_outstanding_transaction() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+    drop(db);
+
+    let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    let err = env.close().unwrap_err();
+    assert_eq!(libdb::ErrorKind::HandlesOutstanding, err.kind());
+
+    // The environment stays open for the transaction, and closes when it finishes.
+    txn.abort().expect("Failed to abort");
+    let (env, db) = open_test_db(dbdir.path());
+    drop(db);
+    env.close().expect("Failed to close");
+}
+
+#[test]
+fn test_cursor_scan_and_delete() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    for name in &["a", "b", "c", "d"] {
+        let key   = name.to_string().into_bytes();
+        let value = if *name < "c" { "stale" } else { "fresh" }.to_string().into_bytes();
+        db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
+    }
+
+    {
+        let txn        = env.txn(None, libdb::TxnFlags::new()).unwrap();
+        let mut cursor = db.write_cursor(Some(&txn), libdb::DB_NONE).unwrap();
+        while let Some((_, value)) = cursor.next().expect("Failed to read") {
+            if value.as_slice() == b"stale" {
+                cursor.del().expect("Failed to delete");
+            }
+        }
+        drop(cursor);
+        txn.commit(libdb::CommitType::Inherit).expect("Commit failed");
+    }
+
+    for name in &["a", "b"] {
+        let key = name.to_string().into_bytes();
+        assert_norecord(&db, key.as_slice());
+    }
+    for name in &["c", "d"] {
+        let key = name.to_string().into_bytes();
+        assert_record_eq(&db, key.as_slice(), "fresh");
+    }
+}
+
+#[test]
+fn test_cursor_end_of_database() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (_env, db) = open_test_db(dbdir.path());
+
+    let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    assert!(cursor.next().expect("Empty database is not an error").is_none());
+    assert!(cursor.first().expect("Empty database is not an error").is_none());
+
+    let key   = String::from("key").into_bytes();
+    let value = String::from("value").into_bytes();
+    db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
+
+    let mut cursor = db.cursor(None, libdb::DB_NONE).unwrap();
+    assert!(cursor.next().unwrap().is_some());
+    assert!(cursor.next().expect("End of database is not an error").is_none());
+    assert!(cursor.last().unwrap().is_some());
+    assert!(cursor.prev().expect("Start of database is not an error").is_none());
+}
+
+#[test]
+fn test_iter_from_both_ends() {
+    let dbdir      = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (_env, db) = open_test_db(dbdir.path());
+
+    for name in &["a", "b", "c", "d", "e"] {
+        let key   = name.to_string().into_bytes();
+        let value = String::from("value").into_bytes();
+        db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
+    }
+
+    let mut iter = db.iter(None, libdb::DB_NONE).unwrap();
+    let mut keys = Vec::new();
+    loop {
+        match iter.next() {
+            Some(record) => keys.push(record.unwrap().0),
+            None         => break,
+        }
+        match iter.next_back() {
+            Some(record) => keys.push(record.unwrap().0),
+            None         => break,
+        }
+    }
+
+    let keys: Vec<&str> = keys.iter().map(|key| str::from_utf8(key).unwrap()).collect();
+    assert_eq!(vec!["a", "e", "b", "d", "c"], keys);
+}
+
+#[test]
+fn test_concurrent_data_store_writes() {
+    let dbdir = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let env   = libdb::EnvironmentBuilder::new()
+        .home(dbdir.path())
+        .flags(libdb::DB_CREATE | libdb::DB_INIT_CDB | libdb::DB_INIT_MPOOL)
+        .open()
+        .expect("Failed to open DB");
+    let db = libdb::DatabaseBuilder::new()
+        .environment(&env)
+        .file("db")
+        .flags(libdb::DB_CREATE)
+        .open()
+        .expect("Failed to open DB");
+
+    for name in &["a", "b", "c"] {
+        let key   = name.to_string().into_bytes();
+        let value = String::from("value").into_bytes();
+        db.put(None, key.as_slice(), value.as_slice(), libdb::DB_NONE).expect("Failed to put");
+    }
+
+    // Both write through a cursor, which must be a write cursor in a CDS environment.
+    let replaced = db.replace(None, b"a", b"new").expect("Failed to replace");
+    assert_eq!(Some(b"value".to_vec()), replaced);
+    let range = libdb::KeyRange::Prefix(b"b");
+    assert_eq!(1, db.delete_range(None, range, 10).expect("Failed to delete"));
+
+    assert_record_eq(&db, b"a", "new");
+    assert_norecord(&db, b"b");
+    assert_record_eq(&db, b"c", "value");
+}
+
+#[test]
+fn test_bulk_writer() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    // Pairs still buffered are stored when the writer is dropped.
+    {
+        let mut writer = db.bulk_writer(None, 64 * 1024);
+        writer.put(b"a", b"1").expect("Failed to put");
+        writer.put(b"b", b"2").expect("Failed to put");
+    }
+    assert_record_eq(&db, b"a", "1");
+    assert_record_eq(&db, b"b", "2");
+
+    // A Recno database would read the buffer as record numbers.
+    let recno = libdb::DatabaseBuilder::new()
+        .environment(&env)
+        .file("recno")
+        .db_type(libdb::DbType::Recno)
+        .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+        .open()
+        .expect("Failed to open DB");
+    let mut writer = recno.bulk_writer(None, 64 * 1024);
+    writer.put(&1u32.to_ne_bytes(), b"value").expect("Failed to put");
+    let einval = libdb::Error::from_kind(libdb::ErrorKind::Db).errno();
+    assert_eq!(Some(einval), writer.finish().err().map(|e| e.errno()));
+}
+
+#[test]
+fn test_bulk_reader_recno() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, _)  = open_test_db(dbdir.path());
+    let recno = libdb::DatabaseBuilder::new()
+        .environment(&env)
+        .file("recno")
+        .db_type(libdb::DbType::Recno)
+        .flags(libdb::DB_CREATE | libdb::DB_AUTO_COMMIT)
+        .open()
+        .expect("Failed to open DB");
+    recno.put(None, &1u32.to_ne_bytes(), b"value", libdb::DB_NONE).expect("Failed to put");
+
+    // The buffer would hold record numbers, not keys.
+    let einval = libdb::Error::from_kind(libdb::ErrorKind::Db).errno();
+    assert_eq!(Some(einval), recno.bulk_reader(None, 16 * 1024).err().map(|e| e.errno()));
+}
+
+#[test]
+fn test_get_many_deleted_record() {
+    let db = libdb::DatabaseBuilder::new()
+        .db_type(libdb::DbType::Queue)
+        .record_len(8)
+        .flags(libdb::DB_CREATE)
+        .open()
+        .expect("Failed to open DB");
+    db.append(None, b"first").expect("Failed to append");
+    db.append(None, b"second").expect("Failed to append");
+    db.consume(None, false).expect("Failed to consume");
+
+    // The consumed record is reported like a missing one, without failing the batch.
+    let keys: Vec<[u8; 4]> = vec![1u32.to_ne_bytes(), 2u32.to_ne_bytes(), 3u32.to_ne_bytes()];
+    let ret = db.get_many(None, keys.iter().map(|key| &key[..]), libdb::DB_NONE).expect("Failed to get");
+    assert!(ret[0].is_none());
+    assert!(ret[1].as_ref().unwrap().as_slice().starts_with(b"second"));
+    assert!(ret[2].is_none());
+}
+
+#[test]
+fn test_cds_group() {
+    let dbdir = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let env   = libdb::EnvironmentBuilder::new()
+        .home(dbdir.path())
+        .flags(libdb::DB_CREATE | libdb::DB_INIT_CDB | libdb::DB_INIT_MPOOL)
+        .open()
+        .expect("Failed to open DB");
+    let db = libdb::DatabaseBuilder::new()
+        .environment(&env)
+        .file("db")
+        .flags(libdb::DB_CREATE)
+        .open()
+        .expect("Failed to open DB");
+
+    let group = env.cdsgroup_begin().expect("Failed to begin group");
+    db.put(Some(&group), b"a", b"1", libdb::DB_NONE).expect("Failed to put");
+    // Groups support none of a transaction's other operations, and stay usable after.
+    assert!(group.set_timeout(std::time::Duration::from_secs(1), libdb::TimeoutKind::Lock).is_err());
+    assert!(group.prepare(&[0; libdb::GID_SIZE]).is_err());
+    db.put(Some(&group), b"b", b"2", libdb::DB_NONE).expect("Failed to put");
+    group.commit().expect("Failed to commit group");
+
+    {
+        let group = env.cdsgroup_begin().expect("Failed to begin group");
+        db.put(Some(&group), b"c", b"3", libdb::DB_NONE).expect("Failed to put");
+    }
+
+    // Both groups released their locks, so a write cursor can be opened outside them.
+    db.put(None, b"d", b"4", libdb::DB_NONE).expect("Failed to put");
+    assert_record_eq(&db, b"a", "1");
+    assert_record_eq(&db, b"c", "3");
+    assert_eq!(0, env.resource_usage().transactions);
+}
+
+#[test]
+fn test_replace_without_locking() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    // The environment has transactions but no lock subsystem, so DB_RMW is unavailable.
+    let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
+    assert_eq!(None, db.replace(Some(&txn), b"key", b"first").expect("Failed to replace"));
+    let replaced = db.replace(Some(&txn), b"key", b"second").expect("Failed to replace");
+    assert_eq!(Some(b"first".to_vec()), replaced);
+    txn.commit(libdb::CommitType::Inherit).expect("Commit failed");
+    assert_record_eq(&db, b"key", "second");
+}
+
+#[test]
+fn test_with_txn_retries_deadlocks() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let (env, db) = open_test_db(dbdir.path());
+
+    let key   = String::from("key").into_bytes();
+    let value = String::from("value").into_bytes();
+
+    // A deadlock victim is retried in a new transaction, its writes undone each time.
+    let mut attempts = 0;
+    let ret: Result<(), libdb::Error> = env.with_txn(libdb::TxnFlags::new(), 2, |txn| {
+        attempts += 1;
+        db.put(Some(txn), key.as_slice(), value.as_slice(), libdb::DB_NONE)?;
+        Err(libdb::Error::new(libdb::error::DB_LOCK_DEADLOCK))
+    });
+    assert_eq!(libdb::error::DB_LOCK_DEADLOCK, ret.unwrap_err().errno());
+    assert_eq!(3, attempts);
+    assert_norecord(&db, key.as_slice());
+
+    // Other errors are not retried.
+    let mut attempts = 0;
+    let ret: Result<(), libdb::Error> = env.with_txn(libdb::TxnFlags::new(), 2, |_| {
+        attempts += 1;
+        Err(libdb::Error::new(libdb::error::DB_NOTFOUND))
+    });
+    assert!(ret.is_err());
+    assert_eq!(1, attempts);
+
+    env.with_txn(libdb::TxnFlags::new(), 2, |txn| {
+        db.put(Some(txn), key.as_slice(), value.as_slice(), libdb::DB_NONE)
+    }).expect("Failed to commit");
+    assert_record_eq(&db, key.as_slice(), "value");
+}
+
+#[test]
+fn test_xa_recover_prepared_branch() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+
+    // Prepare a branch, then leave it unresolved as a crashed process would.
+    {
+        let (env, db) = open_test_db(dbdir.path());
+        let mut xa = libdb::XaResource::new(env.clone());
+        {
+            let txn = xa.start(b"branch").expect("Failed to start");
+            db.put(Some(txn), b"key", b"value", libdb::DB_NONE).expect("Failed to put");
+        }
+        xa.prepare(b"branch").expect("Failed to prepare");
+        xa.forget(b"branch").expect("Failed to forget");
+    }
+
+    {
+        let (env, db) = open_test_db(dbdir.path());
+        let mut xa = libdb::XaResource::new(env.clone());
+        let gids = xa.recover().expect("Failed to recover");
+        assert_eq!(1, gids.len());
+        assert!(gids[0].starts_with(b"branch"));
+        xa.commit(&gids[0]).expect("Failed to commit");
+        assert_record_eq(&db, b"key", "value");
+    }
+}
+
+#[test]
+fn test_data_dir() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    std::fs::create_dir(dbdir.path().join("data")).expect("Failed to create data dir");
+
+    let env = libdb::EnvironmentBuilder::new()
+        .home(dbdir.path())
+        .data_dir("data")
+        .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+        .open()
+        .expect("Failed to open env");
+    let db = libdb::DatabaseBuilder::new()
+        .environment(&env)
+        .file("db")
+        .flags(libdb::DB_CREATE)
+        .open()
+        .expect("Failed to open DB");
+    db.put(None, b"key", b"value", libdb::DB_NONE).expect("Failed to put");
+
+    // The database is created in the data directory, and found there by its relative name.
+    assert!(dbdir.path().join("data").join("db").exists());
+    assert!(!dbdir.path().join("db").exists());
+}
+
+#[test]
+fn test_fileid_reset() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let env = libdb::EnvironmentBuilder::new()
+        .home(dbdir.path())
+        .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+        .open()
+        .expect("Failed to open env");
+
+    {
+        let db = libdb::DatabaseBuilder::new()
+            .environment(&env)
+            .file("original")
+            .flags(libdb::DB_CREATE)
+            .open()
+            .expect("Failed to open DB");
+        db.put(None, b"key", b"value", libdb::DB_NONE).expect("Failed to put");
+    }
+    std::fs::copy(dbdir.path().join("original"), dbdir.path().join("copy")).expect("Failed to copy");
+    env.fileid_reset("copy", false).expect("Failed to reset file ID");
+
+    let open = |file| libdb::DatabaseBuilder::new()
+        .environment(&env)
+        .file(file)
+        .open()
+        .expect("Failed to open DB");
+    let original = open("original");
+    let copy = open("copy");
+
+    // The copy no longer shares the original's pages in the cache.
+    copy.put(None, b"key", b"changed", libdb::DB_NONE).expect("Failed to put");
+    assert_record_eq(&original, b"key", "value");
+    assert_record_eq(&copy, b"key", "changed");
+}
+
+#[test]
+fn test_tx_timestamp_before_epoch() {
+    let dbdir     = TempDir::new("libdb-rs").expect("Expected temp dir");
+    let before_epoch = std::time::UNIX_EPOCH - std::time::Duration::from_secs(1);
+    let ret = libdb::EnvironmentBuilder::new()
+        .home(dbdir.path())
+        .flags(libdb::DB_CREATE | libdb::DB_INIT_MPOOL)
+        .tx_timestamp(before_epoch)
+        .open();
+    let einval = libdb::Error::from_kind(libdb::ErrorKind::Db).errno();
+    assert_eq!(Some(einval), ret.err().map(|e| e.errno()));
 }
 
 /// Helper to open a BDB environment for the test.
@@ -71,7 +574,7 @@ fn open_test_db(dir: &Path) -> (libdb::Environment, libdb::Database) {
         .open()
         .expect("Failed to open DB");
 
-    let txn = env.txn(None, libdb::DB_NONE).unwrap();
+    let txn = env.txn(None, libdb::TxnFlags::new()).unwrap();
     let ret = libdb::DatabaseBuilder::new()
         .transaction(&txn)
         .environment(&env)
@@ -88,12 +591,12 @@ fn open_test_db(dir: &Path) -> (libdb::Environment, libdb::Database) {
 }
 
 /// Helper to assert a record is missing in the database.
-fn assert_norecord(db: &libdb::Database, key: &mut [u8]) {
+fn assert_norecord(db: &libdb::Database, key: &[u8]) {
     assert!(db.get(None, key, libdb::DB_NONE).unwrap().is_none());
 }
 
 /// Helper to assert a record has a specific value in the database.
-fn assert_record_eq(db: &libdb::Database, key: &mut [u8], expected :&str) {
+fn assert_record_eq(db: &libdb::Database, key: &[u8], expected :&str) {
     match db.get(None, key, libdb::DB_NONE) {
         Ok(Some(value)) => assert_eq!(expected, str::from_utf8(value.as_slice()).unwrap()),
         _               => assert!(false)